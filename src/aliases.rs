@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::framedata::{normalize, Character, FrameData};
+
+pub const DEFAULT_ALIASES_PATH: &str = "aliases.toml";
+// bumped whenever the layout of the file changes
+pub const ALIASES_VERSION: u32 = 1;
+// the moves table that applies to every character
+pub const ANY_CHARACTER: &str = "*";
+
+// Community nicknames for characters and moves, e.g. "gio" for Giovanna or "dp" for Sol's
+// Volcanic Viper. Channels can override the global ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AliasFile {
+    pub version: u32,
+    #[serde(flatten)]
+    pub global: AliasSet,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub channel: BTreeMap<String, AliasSet>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AliasSet {
+    // nickname -> character name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub characters: BTreeMap<String, String>,
+    // character (or "*" for all of them) -> nickname -> move query
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub moves: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+// written at the top of the local file, which is rewritten on every change
+const LOCAL_HEADER: &str = "# Written by the bot whenever moderators use !alias or !unalias. It is only read on startup,\n\
+                            # so edit it while the bot is stopped or the next change will overwrite your edits.\n\
+                            # These win over the shipped aliases file they sit next to.\n";
+
+// The shipped file is only ever read. What moderators change at runtime goes to a separate local
+// file next to it, e.g. aliases.local.toml, which is layered on top.
+#[derive(Debug, Default)]
+pub struct Aliases {
+    shipped: AliasFile,
+    local: AliasFile,
+    // where runtime changes are saved, if anywhere
+    local_path: Option<PathBuf>,
+}
+
+impl Aliases {
+    // Missing files are fine and start out empty, broken ones are an error
+    pub fn load(path: Option<PathBuf>) -> Result<Aliases, Box<dyn Error>> {
+        let local_path = path.as_ref().map(|path| path.with_extension("local.toml"));
+        Ok(Aliases { shipped: read_or_empty(path.as_deref())?, local: read_or_empty(local_path.as_deref())?, local_path })
+    }
+
+    // What the character query stands for, or the query itself if it isn't a nickname
+    pub fn character(&self, channel: &str, query: &str) -> String {
+        self.sets(channel).into_iter()
+            .find_map(|set| lookup(&set.characters, query))
+            .unwrap_or_else(|| query.to_string())
+    }
+
+    // What the move query stands for with `character`, or the query itself if it isn't a nickname.
+    // Channel aliases win over global ones and character specific ones over the "*" table.
+    pub fn move_query(&self, channel: &str, data: &FrameData, character: &Character, query: &str) -> String {
+        for set in self.sets(channel) {
+            let specific = set.moves.iter()
+                .filter(|(key, _)| key.as_str() != ANY_CHARACTER)
+                .filter(|(key, _)| data.find_character(key).map(|c| c.name == character.name).unwrap_or(false))
+                .find_map(|(_, moves)| lookup(moves, query));
+            let any = || set.moves.get(ANY_CHARACTER).and_then(|moves| lookup(moves, query));
+            if let Some(found) = specific.or_else(any) {
+                return found;
+            }
+        }
+        query.to_string()
+    }
+
+    pub fn add_character(&mut self, channel: &str, nickname: &str, name: &str) -> Result<(), Box<dyn Error>> {
+        self.channel_set(channel).characters.insert(nickname.to_lowercase(), name.to_string());
+        self.save()
+    }
+
+    pub fn add_move(&mut self, channel: &str, character: &str, nickname: &str, query: &str) -> Result<(), Box<dyn Error>> {
+        self.channel_set(channel).moves.entry(character.to_string()).or_default().insert(nickname.to_lowercase(), query.to_string());
+        self.save()
+    }
+
+    // Returns whether there was such an alias in the channel. Only runtime changes can be removed,
+    // what the shipped file has can be overridden with another alias instead.
+    pub fn remove_character(&mut self, channel: &str, nickname: &str) -> Result<bool, Box<dyn Error>> {
+        let Some(set) = self.local.channel.get_mut(channel) else {
+            return Ok(false);
+        };
+        if set.characters.remove(&nickname.to_lowercase()).is_none() {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn remove_move(&mut self, channel: &str, character: &str, nickname: &str) -> Result<bool, Box<dyn Error>> {
+        let Some(set) = self.local.channel.get_mut(channel) else {
+            return Ok(false);
+        };
+        let removed = set.moves.get_mut(character).map(|moves| moves.remove(&nickname.to_lowercase()).is_some()).unwrap_or(false);
+        if !removed {
+            return Ok(false);
+        }
+        set.moves.retain(|_, moves| !moves.is_empty());
+        self.save()?;
+        Ok(true)
+    }
+
+    // most specific first: the channel's local and shipped aliases, then the global ones
+    fn sets(&self, channel: &str) -> Vec<&AliasSet> {
+        [&self.local, &self.shipped].into_iter().filter_map(|file| file.channel.get(channel))
+            .chain([&self.local.global, &self.shipped.global])
+            .collect()
+    }
+
+    fn channel_set(&mut self, channel: &str) -> &mut AliasSet {
+        self.local.channel.entry(channel.to_string()).or_default()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.local_path else {
+            return Ok(());
+        };
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, format!("{}{}", LOCAL_HEADER, toml::to_string(&self.local)?))?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+fn read_or_empty(path: Option<&Path>) -> Result<AliasFile, Box<dyn Error>> {
+    match path {
+        Some(path) if path.exists() => read(path),
+        _ => Ok(AliasFile { version: ALIASES_VERSION, ..AliasFile::default() }),
+    }
+}
+
+fn read(path: &Path) -> Result<AliasFile, Box<dyn Error>> {
+    let raw = std::fs::read_to_string(path)?;
+    let file: AliasFile = toml::from_str(&raw).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if file.version > ALIASES_VERSION {
+        return Err(format!("{} is version {}, only up to {} is supported", path.display(), file.version, ALIASES_VERSION).into());
+    }
+    Ok(file)
+}
+
+// nicknames compare like move queries do, so "c.K" and "ck" are the same alias
+fn lookup(table: &BTreeMap<String, String>, query: &str) -> Option<String> {
+    let query = normalize(query);
+    if query.is_empty() {
+        return None;
+    }
+    table.iter().find(|(nickname, _)| normalize(nickname) == query).map(|(_, target)| target.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AliasFile, Aliases};
+    use crate::framedata::{Character, FrameData, Move};
+
+    fn file(raw: &str) -> AliasFile {
+        toml::from_str(raw).unwrap()
+    }
+
+    fn data() -> FrameData {
+        FrameData {
+            characters: vec![
+                Character { name: "Sol Badguy".to_string(), moves: vec![Move::test("623S").named("Volcanic Viper")] },
+                Character { name: "Ky Kiske".to_string(), moves: vec![Move::test("623S").named("Vapor Thrust")] },
+            ],
+        }
+    }
+
+    fn aliases() -> Aliases {
+        let shipped = file(r#"
+            version = 1
+            characters = { a = "global shipped", b = "global shipped", c = "global shipped", d = "global shipped" }
+            [channel.foo]
+            characters = { a = "channel shipped", b = "channel shipped" }
+        "#);
+        let local = file(r#"
+            version = 1
+            characters = { a = "global local", b = "global local", c = "global local" }
+            [channel.foo]
+            characters = { a = "channel local" }
+        "#);
+        Aliases { shipped, local, local_path: None }
+    }
+
+    #[test]
+    fn channel_local_then_channel_shipped_then_global_local_then_global_shipped() {
+        let aliases = aliases();
+        assert_eq!(aliases.character("foo", "a"), "channel local");
+        assert_eq!(aliases.character("foo", "b"), "channel shipped");
+        assert_eq!(aliases.character("foo", "c"), "global local");
+        assert_eq!(aliases.character("foo", "d"), "global shipped");
+        assert_eq!(aliases.character("bar", "a"), "global local");
+        assert_eq!(aliases.character("foo", "e"), "e");
+    }
+
+    #[test]
+    fn character_moves_win_over_the_any_table() {
+        let shipped = file(r#"
+            version = 1
+            [moves]
+            "*" = { dp = "623P" }
+            sol = { dp = "623S" }
+        "#);
+        let aliases = Aliases { shipped, ..Aliases::default() };
+        let data = data();
+        let sol = data.find_character("sol").unwrap();
+        let ky = data.find_character("ky").unwrap();
+        assert_eq!(aliases.move_query("foo", &data, sol, "dp"), "623S");
+        assert_eq!(aliases.move_query("foo", &data, ky, "DP"), "623P");
+        assert_eq!(aliases.move_query("foo", &data, ky, "5k"), "5k");
+    }
+
+    #[test]
+    fn only_local_channel_aliases_are_removed() {
+        let mut aliases = aliases();
+        assert!(!aliases.remove_character("foo", "b").unwrap());
+        assert!(!aliases.remove_character("bar", "a").unwrap());
+        assert!(!aliases.local.channel.contains_key("bar"));
+        assert!(aliases.remove_character("foo", "A").unwrap());
+        assert_eq!(aliases.character("foo", "a"), "channel shipped");
+        assert!(!aliases.remove_move("foo", "sol", "dp").unwrap());
+    }
+}
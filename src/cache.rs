@@ -0,0 +1,40 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::framedata::FrameData;
+
+pub const DEFAULT_CACHE_PATH: &str = "framedata.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedData {
+    // seconds since the unix epoch of when the data was scraped
+    pub fetched_at: u64,
+    pub data: FrameData,
+}
+
+impl CachedData {
+    pub fn age(&self) -> Duration {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        now.saturating_sub(Duration::from_secs(self.fetched_at))
+    }
+}
+
+pub fn read(path: &Path) -> Result<CachedData, Box<dyn Error>> {
+    let raw = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&raw)?)
+}
+
+// Writes to a temporary file first so a crash mid-write never leaves a truncated cache behind
+pub fn write(path: &Path, data: &FrameData) -> Result<(), Box<dyn Error>> {
+    let cached = CachedData {
+        fetched_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        data: data.clone(),
+    };
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_string(&cached)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::irc::IrcMessage;
+use crate::reconnect::FatalError;
+use crate::WsStream;
+
+// sender badges and message ids, which moderator checks and threaded replies depend on
+pub const TAGS: &str = "twitch.tv/tags";
+// a server that hasn't answered the CAP REQ by then is treated like a dropped connection
+const NEGOTIATE_TIMEOUT: Duration = Duration::from_secs(15);
+
+// The set of capabilities the server acknowledged during login
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    acknowledged: HashSet<String>,
+}
+
+impl Capabilities {
+    #[cfg(test)]
+    pub fn acknowledged(capabilities: &[&str]) -> Capabilities {
+        Capabilities { acknowledged: capabilities.iter().map(|c| c.to_string()).collect() }
+    }
+
+    pub fn has(&self, capability: &str) -> bool {
+        self.acknowledged.contains(capability)
+    }
+}
+
+// Sends the CAP REQ, then the credentials, and waits for the server to ACK or NAK the request.
+// Anything else received before that (welcome numerics etc.) is only logged.
+pub async fn negotiate(ws_stream: &mut WsStream, requested: &[String], pass: &str, nick: &str) -> Result<Capabilities, Box<dyn Error>> {
+    if !requested.is_empty() {
+        ws_stream.send(Message::Text(format!("CAP REQ :{}", requested.join(" ")))).await?;
+    }
+    ws_stream.send(Message::Text(format!("PASS {}", pass))).await?;
+    ws_stream.send(Message::Text(format!("NICK {}", nick))).await?;
+
+    if requested.is_empty() {
+        return Ok(Capabilities::default());
+    }
+
+    tokio::time::timeout(NEGOTIATE_TIMEOUT, wait_for_answer(ws_stream, requested)).await
+        .map_err(|_| format!("No answer to CAP REQ within {}s", NEGOTIATE_TIMEOUT.as_secs()))?
+}
+
+async fn wait_for_answer(ws_stream: &mut WsStream, requested: &[String]) -> Result<Capabilities, Box<dyn Error>> {
+    while let Some(msg) = ws_stream.next().await {
+        let msg = msg?;
+        let Ok(text) = msg.to_text() else {
+            continue;
+        };
+        if let Some(capabilities) = read_frame(text, requested)? {
+            return Ok(capabilities);
+        }
+    }
+
+    Err("Connection closed during capability negotiation".into())
+}
+
+// The answer to the CAP REQ if the frame has one. Twitch may send the ACK and a failed login in
+// the same frame, so every line is looked at before the answer counts.
+fn read_frame(text: &str, requested: &[String]) -> Result<Option<Capabilities>, Box<dyn Error>> {
+    let mut answer = None;
+    for line in text.lines().filter(|l| !l.is_empty()) {
+        println!("{}", line);
+        let Some(message) = IrcMessage::parse(line) else {
+            continue;
+        };
+        if let Some(reason) = login_failure(&message) {
+            return Err(Box::new(FatalError(format!("Login rejected: {}", reason))));
+        }
+        if message.command != "CAP" || answer.is_some() {
+            continue;
+        }
+        // CAP * ACK :twitch.tv/tags twitch.tv/commands
+        let listed = message.trailing().unwrap_or("").split_whitespace().map(|s| s.to_string());
+        match message.param(1) {
+            Some("ACK") => answer = Some(Capabilities { acknowledged: listed.collect() }),
+            Some("NAK") => {
+                println!("Capabilities rejected by server: {}", requested.join(" "));
+                answer = Some(Capabilities::default());
+            }
+            _ => {}
+        }
+    }
+    Ok(answer)
+}
+
+// Twitch rejects bad credentials with a NOTICE that isn't addressed to any channel
+pub fn login_failure(message: &IrcMessage) -> Option<&str> {
+    if message.command == "NOTICE" && message.param(0) == Some("*") {
+        return message.trailing();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_frame, TAGS};
+    use crate::reconnect::FatalError;
+
+    fn requested() -> Vec<String> {
+        vec![TAGS.to_string(), "twitch.tv/commands".to_string()]
+    }
+
+    #[test]
+    fn acknowledged_capabilities() {
+        let capabilities = read_frame(":tmi.twitch.tv CAP * ACK :twitch.tv/tags twitch.tv/commands\r\n", &requested()).unwrap().unwrap();
+        assert!(capabilities.has(TAGS));
+        assert!(capabilities.has("twitch.tv/commands"));
+        assert!(!capabilities.has("twitch.tv/membership"));
+    }
+
+    #[test]
+    fn rejected_capabilities() {
+        let capabilities = read_frame(":tmi.twitch.tv CAP * NAK :twitch.tv/tags twitch.tv/commands\r\n", &requested()).unwrap().unwrap();
+        assert!(!capabilities.has(TAGS));
+    }
+
+    #[test]
+    fn no_answer_yet() {
+        assert!(read_frame(":tmi.twitch.tv 001 bot :Welcome, GLHF!\r\n", &requested()).unwrap().is_none());
+    }
+
+    #[test]
+    fn failed_login_after_ack_in_the_same_frame() {
+        let frame = ":tmi.twitch.tv CAP * ACK :twitch.tv/tags\r\n:tmi.twitch.tv NOTICE * :Login authentication failed\r\n";
+        let error = read_frame(frame, &requested()).unwrap_err();
+        assert!(error.is::<FatalError>());
+    }
+}
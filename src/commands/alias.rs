@@ -0,0 +1,95 @@
+use crate::aliases::Aliases;
+use crate::notation;
+use crate::search::{self, SearchResult};
+
+use super::{Command, CommandHandler, Context};
+
+// Lets moderators manage the nicknames of their channel:
+// !alias char <nickname> = <character>
+// !alias move <character> <nickname> = <move>
+// !unalias char <nickname>
+// !unalias move <character> <nickname>
+pub struct AliasCommand;
+
+impl CommandHandler for AliasCommand {
+    fn names(&self) -> &[&'static str] {
+        &["alias", "unalias"]
+    }
+
+    fn handle(&self, command: &Command, ctx: &Context) -> Vec<String> {
+        if !command.is_moderator() {
+            println!("{} tried to change aliases in #{} without being a moderator", command.sender, command.channel);
+            return vec![];
+        }
+
+        let mut aliases = ctx.state.aliases.write().unwrap_or_else(|e| e.into_inner());
+        let reply = if command.command.eq_ignore_ascii_case("unalias") {
+            remove(command, ctx, &mut aliases)
+        } else {
+            add(command, ctx, &mut aliases)
+        };
+        vec![reply.unwrap_or_else(|err| err)]
+    }
+}
+
+fn add(command: &Command, ctx: &Context, aliases: &mut Aliases) -> Result<String, String> {
+    let prefix = ctx.config.command_prefix(&command.channel);
+    let args = command.args.join(" ");
+    let usage = || format!("Try: {p}alias char <nickname> = <character> or {p}alias move <character> <nickname> = <move>", p = prefix);
+    let (kind, rest) = args.split_once(' ').ok_or_else(usage)?;
+    let (left, target) = rest.split_once('=').ok_or_else(usage)?;
+    let (left, target) = (left.trim(), target.trim());
+    if left.is_empty() || target.is_empty() {
+        return Err(usage());
+    }
+
+    match kind.to_ascii_lowercase().as_str() {
+        "char" | "character" => {
+            let character = ctx.data.find_character(target).ok_or_else(|| format!("Unknown character '{}'", target))?;
+            aliases.add_character(&command.channel, left, &character.name).map_err(save_failed)?;
+            Ok(format!("'{}' now means {}", left, character.name))
+        },
+        "move" => {
+            let (character_query, nickname) = left.split_once(' ').ok_or_else(usage)?;
+            let character = ctx.data.find_character(character_query).ok_or_else(|| format!("Unknown character '{}'", character_query))?;
+            let found = match search::find_move(character, &notation::canonical(target)) {
+                SearchResult::Found(found) => found,
+                SearchResult::Ambiguous(candidates) => {
+                    let inputs = candidates.iter().map(|c| c.found.input.as_str()).collect::<Vec<&str>>();
+                    return Err(format!("'{}' for {} could be: {}", target, character.name, inputs.join(", ")));
+                },
+                SearchResult::NotFound => return Err(format!("Unknown move '{}' for {}", target, character.name)),
+            };
+            aliases.add_move(&command.channel, &character.name, nickname.trim(), &found.input).map_err(save_failed)?;
+            Ok(format!("'{}' now means {} {}", nickname.trim(), character.name, found.input))
+        },
+        _ => Err(usage()),
+    }
+}
+
+fn remove(command: &Command, ctx: &Context, aliases: &mut Aliases) -> Result<String, String> {
+    let prefix = ctx.config.command_prefix(&command.channel);
+    let usage = || format!("Try: {p}unalias char <nickname> or {p}unalias move <character> <nickname>", p = prefix);
+    let (kind, rest) = command.args.split_first().ok_or_else(usage)?;
+
+    let removed = match kind.to_ascii_lowercase().as_str() {
+        "char" | "character" if rest.len() == 1 => {
+            aliases.remove_character(&command.channel, &rest[0]).map_err(save_failed)?
+        },
+        "move" if rest.len() >= 2 => {
+            let character = ctx.data.find_character(&rest[0]).ok_or_else(|| format!("Unknown character '{}'", rest[0]))?;
+            aliases.remove_move(&command.channel, &character.name, &rest[1..].join(" ")).map_err(save_failed)?
+        },
+        _ => return Err(usage()),
+    };
+    if removed {
+        Ok("Alias removed".to_string())
+    } else {
+        Err("No such alias in this channel".to_string())
+    }
+}
+
+fn save_failed(err: Box<dyn std::error::Error>) -> String {
+    eprintln!("Could not save aliases: {}", err);
+    "Alias changed but could not be saved, it will be lost on restart".to_string()
+}
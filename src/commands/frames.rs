@@ -0,0 +1,130 @@
+use std::time::Instant;
+
+use crate::aliases::Aliases;
+use crate::framedata::{Character, FrameData, Move, MoveField};
+use crate::notation;
+use crate::search::{self, SearchResult};
+use crate::suggest;
+
+use super::{Command, CommandHandler, Context};
+
+// !fd <character> <move> [field]
+pub struct FramesCommand;
+
+impl CommandHandler for FramesCommand {
+    fn names(&self) -> &[&'static str] {
+        &["fd", "frames"]
+    }
+
+    fn handle(&self, command: &Command, ctx: &Context) -> Vec<String> {
+        let aliases = ctx.state.aliases.read().unwrap_or_else(|e| e.into_inner());
+        let reply = match parse_frames_command(command.args.clone(), &ctx.data, &aliases, &command.channel) {
+            Ok(query) => {
+                // dedup on the resolved move so "sol 5k" and "sol 5K" count as the same question
+                let asked = match query.field {
+                    Some(field) => format!("{} {}", query.found.input, field.label()),
+                    None => query.found.input.clone(),
+                };
+                let window = ctx.config.dedup_window(&command.channel);
+                let mut dedup = ctx.state.dedup.lock().unwrap_or_else(|e| e.into_inner());
+                if dedup.is_duplicate(&command.channel, &query.character.name, &asked, window, Instant::now()) {
+                    println!("Skipping duplicate lookup of {} {} in #{}", query.character.name, asked, command.channel);
+                    return vec![];
+                }
+                match query.field {
+                    Some(field) => format!("{} {} {}: {}", query.character.name, query.found.input, field.label(), field.value(query.found)),
+                    None => ctx.config.template(&command.channel).render(query.character, query.found),
+                }
+            },
+            Err(err) => describe_error(err, format!("{}fd <char> <move_query> [field]", ctx.config.command_prefix(&command.channel))),
+        };
+        vec![reply]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ParseFramesCommandError<'a> {
+    UnknownCharacter { query: String, suggestions: Vec<&'a str> },
+    UnknownMove { character: &'a str, query: String, suggestions: Vec<&'a str> },
+    AmbiguousMove { character: &'a str, query: String, candidates: Vec<&'a Move> },
+    WrongArguments,
+}
+
+// The reply for a failed lookup, `usage` is how the command should have been written
+pub fn describe_error(err: ParseFramesCommandError, usage: String) -> String {
+    match err {
+        ParseFramesCommandError::UnknownCharacter { query, suggestions } => {
+            format!("Unknown character '{}'{}", query, suggest::did_you_mean(&suggestions))
+        },
+        ParseFramesCommandError::UnknownMove { character, query, suggestions } => {
+            format!("Unknown move '{}' for {}{}", query, character, suggest::did_you_mean(&suggestions))
+        },
+        ParseFramesCommandError::AmbiguousMove { character, query, candidates } => {
+            let inputs = candidates.iter().map(|m| m.input.as_str()).collect::<Vec<&str>>();
+            format!("'{}' for {} could be: {}", query, character, inputs.join(", "))
+        },
+        ParseFramesCommandError::WrongArguments => format!("Invalid args, try: {}", usage),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameQuery<'a> {
+    pub character: &'a Character,
+    pub found: &'a Move,
+    // only this column was asked for
+    pub field: Option<MoveField>,
+}
+
+// Nicknames from `aliases` are resolved and notation like "qcf+p" rewritten to Dustloop's
+// "236P" before anything is looked up in `data`
+pub fn parse_frames_command<'a>(args: Vec<String>, data: &'a FrameData, aliases: &Aliases, channel: &str) -> Result<FrameQuery<'a>, ParseFramesCommandError<'a>> {
+    let mut iter = args.into_iter();
+
+    let character_query = iter.next().ok_or(ParseFramesCommandError::WrongArguments)?;
+
+    let move_args = iter.collect::<Vec<String>>();
+    let move_query = move_args.join(" ");
+    if move_query.is_empty() {
+        return Err(ParseFramesCommandError::WrongArguments);
+    }
+
+    let character = data.find_character(&aliases.character(channel, &character_query)).ok_or_else(|| ParseFramesCommandError::UnknownCharacter {
+        suggestions: suggest::characters(data, &character_query),
+        query: character_query.clone(),
+    })?;
+    let resolve = |query: &str| notation::canonical(&aliases.move_query(channel, data, character, query));
+    let full_query = resolve(&move_query);
+
+    // A trailing field keyword only counts as one if the whole query isn't itself the name of a
+    // move, so a move that happens to end in e.g. "Hit" is still found
+    if let Some((last, rest)) = move_args.split_last().filter(|(_, rest)| !rest.is_empty()) {
+        if let Some(field) = MoveField::from_keyword(last) {
+            if character.find_move_exact(&full_query).is_none() {
+                let rest = rest.join(" ");
+                match search::find_move(character, &resolve(&rest)) {
+                    SearchResult::Found(found) => return Ok(FrameQuery { character, found, field: Some(field) }),
+                    SearchResult::Ambiguous(candidates) => return Err(ambiguous(character, rest, candidates)),
+                    SearchResult::NotFound => {},
+                }
+            }
+        }
+    }
+
+    match search::find_move(character, &full_query) {
+        SearchResult::Found(found) => Ok(FrameQuery { character, found, field: None }),
+        SearchResult::Ambiguous(candidates) => Err(ambiguous(character, move_query, candidates)),
+        SearchResult::NotFound => Err(ParseFramesCommandError::UnknownMove {
+            character: &character.name,
+            suggestions: suggest::moves(character, &move_query),
+            query: move_query,
+        }),
+    }
+}
+
+fn ambiguous<'a>(character: &'a Character, query: String, candidates: Vec<search::Candidate<'a>>) -> ParseFramesCommandError<'a> {
+    ParseFramesCommandError::AmbiguousMove {
+        character: &character.name,
+        query,
+        candidates: candidates.into_iter().map(|c| c.found).collect(),
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use crate::aliases::Aliases;
+use crate::capabilities::{self, Capabilities};
+use crate::config::Config;
+use crate::cooldown::Cooldowns;
+use crate::dedup::Dedup;
+use crate::framedata::FrameData;
+use crate::irc::IrcMessage;
+
+mod alias;
+mod frames;
+mod punish;
+
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub channel: String,
+    // the command name without the channel's command prefix
+    pub command: String,
+    pub args: Vec<String>,
+    // login name of whoever sent the command, always lowercase
+    pub sender: String,
+    pub display_name: String,
+    // the following are only present when the tags capability is acknowledged
+    pub message_id: Option<String>,
+    pub badges: Vec<(String, String)>,
+    #[allow(dead_code)] // no command needs it yet
+    pub room_id: Option<String>,
+}
+
+impl Command {
+    pub fn is_moderator(&self) -> bool {
+        self.badges.iter().any(|(badge, _)| badge == "moderator" || badge == "broadcaster")
+    }
+}
+
+// Everything a handler may need to answer a command
+pub struct Context {
+    // what the connection the command came in on acknowledged
+    pub capabilities: Arc<Capabilities>,
+    pub data: Arc<FrameData>,
+    pub config: Arc<Config>,
+    pub state: Arc<State>,
+}
+
+// Bookkeeping that outlives a single command and survives reconnects
+#[derive(Default)]
+pub struct State {
+    pub cooldowns: Mutex<Cooldowns>,
+    pub dedup: Mutex<Dedup>,
+    pub aliases: RwLock<Aliases>,
+}
+
+impl State {
+    pub fn new(aliases: Aliases) -> State {
+        State { aliases: RwLock::new(aliases), ..State::default() }
+    }
+}
+
+pub trait CommandHandler: Send + Sync {
+    // the first name is the one shown in help text, the rest are aliases
+    fn names(&self) -> &[&'static str];
+
+    // returns the replies to send to the channel the command came from, in order
+    fn handle(&self, command: &Command, ctx: &Context) -> Vec<String>;
+}
+
+#[derive(Default)]
+pub struct Registry {
+    handlers: HashMap<String, Arc<dyn CommandHandler>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        let mut registry = Registry::default();
+        registry.register(frames::FramesCommand);
+        registry.register(alias::AliasCommand);
+        registry.register(punish::PunishCommand);
+        registry
+    }
+
+    pub fn register(&mut self, handler: impl CommandHandler + 'static) {
+        let handler: Arc<dyn CommandHandler> = Arc::new(handler);
+        for name in handler.names() {
+            if self.handlers.insert(name.to_ascii_lowercase(), handler.clone()).is_some() {
+                println!("Command name '{}' registered more than once, the last one wins", name);
+            }
+        }
+    }
+
+    pub fn dispatch(&self, command: &Command, ctx: &Context) -> Vec<String> {
+        let Some(handler) = self.handlers.get(&command.command.to_ascii_lowercase()) else {
+            return vec![];
+        };
+
+        // cooldowns are configured under the main name so aliases share them
+        let name = handler.names()[0];
+        if let Some(settings) = ctx.config.cooldown(&command.channel, name) {
+            // without tags there are no badges to tell moderators apart
+            let exempt = settings.exempt_mods && ctx.capabilities.has(capabilities::TAGS) && command.is_moderator();
+            if !exempt {
+                let mut cooldowns = ctx.state.cooldowns.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(left) = cooldowns.check(settings, &command.channel, name, &command.sender, Instant::now()) {
+                    println!("{} used {} on cooldown in #{}, {}s left", command.sender, name, command.channel, left.as_secs());
+                    if settings.notify && cooldowns.should_notify(&command.channel, name, &command.sender, left, Instant::now()) {
+                        return vec![format!("@{} {} is on cooldown for {}s", command.display_name, name, left.as_millis().div_ceil(1000))];
+                    }
+                    return vec![];
+                }
+            }
+        }
+
+        handler.handle(command, ctx)
+    }
+}
+
+pub fn parse_message_to_command(message: &IrcMessage, config: &Config) -> Option<Command> {
+    if message.command != "PRIVMSG" {
+        return None;
+    }
+
+    let channel = message.channel()?.to_string();
+    let msg = message.param(1)?;
+    if let Some(msg) = msg.strip_prefix(config.command_prefix(&channel)) {
+        let mut split = msg.split_whitespace();
+        let root = split.next()?.to_string(); // if no args then this is here
+        let args = split.map(|s| s.to_string()).collect::<Vec<String>>();
+
+        let sender = message.nick()?.to_ascii_lowercase();
+        let display_name = message.tag("display-name").unwrap_or(&sender).to_string();
+        return Some(Command {
+            channel,
+            command: root,
+            args,
+            display_name,
+            sender,
+            message_id: message.tag("id").map(|s| s.to_string()),
+            badges: message.badges(),
+            room_id: message.tag("room-id").map(|s| s.to_string()),
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::capabilities::{self, Capabilities};
+    use crate::config::Config;
+    use crate::framedata::FrameData;
+
+    use super::{Command, CommandHandler, Context, Registry, State};
+
+    struct Ping;
+
+    impl CommandHandler for Ping {
+        fn names(&self) -> &[&'static str] {
+            &["ping"]
+        }
+
+        fn handle(&self, _: &Command, _: &Context) -> Vec<String> {
+            vec!["pong".to_string()]
+        }
+    }
+
+    fn context(cooldown: &str) -> Context {
+        let config = Config::parse(&format!(r#"
+nick = "bot"
+token = {{ value = "oauth:x" }}
+channels = ["sagan37"]
+
+[cooldowns.ping]
+{}
+"#, cooldown)).unwrap();
+        Context {
+            capabilities: Arc::new(Capabilities::acknowledged(&[capabilities::TAGS])),
+            data: Arc::new(FrameData::default()),
+            config: Arc::new(config),
+            state: Arc::new(State::default()),
+        }
+    }
+
+    fn command(sender: &str, badges: &[&str]) -> Command {
+        Command {
+            channel: "sagan37".to_string(),
+            command: "ping".to_string(),
+            args: vec![],
+            sender: sender.to_string(),
+            display_name: sender.to_string(),
+            message_id: None,
+            badges: badges.iter().map(|b| (b.to_string(), "1".to_string())).collect(),
+            room_id: None,
+        }
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry::default();
+        registry.register(Ping);
+        registry
+    }
+
+    #[test]
+    fn cooldown_notice_is_sent_once() {
+        let (registry, ctx) = (registry(), context("user_secs = 30\nnotify = true"));
+        assert_eq!(registry.dispatch(&command("viewer", &[]), &ctx), ["pong"]);
+        assert_eq!(registry.dispatch(&command("viewer", &[]), &ctx), ["@viewer ping is on cooldown for 30s"]);
+        assert!(registry.dispatch(&command("viewer", &[]), &ctx).is_empty());
+        assert_eq!(registry.dispatch(&command("other", &[]), &ctx), ["pong"]);
+    }
+
+    #[test]
+    fn moderators_skip_cooldowns_unless_configured_not_to() {
+        let (registry, ctx) = (registry(), context("channel_secs = 30"));
+        assert_eq!(registry.dispatch(&command("viewer", &[]), &ctx), ["pong"]);
+        assert!(registry.dispatch(&command("viewer2", &[]), &ctx).is_empty());
+        assert_eq!(registry.dispatch(&command("mod", &["moderator"]), &ctx), ["pong"]);
+        assert_eq!(registry.dispatch(&command("streamer", &["broadcaster"]), &ctx), ["pong"]);
+
+        let ctx = context("channel_secs = 30\nexempt_mods = false");
+        assert_eq!(registry.dispatch(&command("mod", &["moderator"]), &ctx), ["pong"]);
+        assert!(registry.dispatch(&command("mod", &["moderator"]), &ctx).is_empty());
+    }
+}
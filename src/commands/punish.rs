@@ -0,0 +1,127 @@
+use std::cmp::Ordering;
+
+use crate::frame_value::{FrameValue, Frames};
+use crate::framedata::{Character, Move};
+use crate::suggest;
+
+use super::frames::{describe_error, parse_frames_command, ParseFramesCommandError};
+use super::{Command, CommandHandler, Context};
+
+// more than this many punishes don't fit in a chat message, the best ones are listed first
+const MAX_PUNISHES: usize = 8;
+
+// !punish <attacker> <move> <defender>
+pub struct PunishCommand;
+
+impl CommandHandler for PunishCommand {
+    fn names(&self) -> &[&'static str] {
+        &["punish"]
+    }
+
+    fn handle(&self, command: &Command, ctx: &Context) -> Vec<String> {
+        let usage = format!("{}punish <attacker> <move> <defender>", ctx.config.command_prefix(&command.channel));
+        let Some((defender_query, attack)) = command.args.split_last().filter(|(_, attack)| attack.len() >= 2) else {
+            return vec![describe_error(ParseFramesCommandError::WrongArguments, usage)];
+        };
+
+        let aliases = ctx.state.aliases.read().unwrap_or_else(|e| e.into_inner());
+        let query = match parse_frames_command(attack.to_vec(), &ctx.data, &aliases, &command.channel) {
+            Ok(query) => query,
+            Err(err) => return vec![describe_error(err, usage)],
+        };
+        let Some(defender) = ctx.data.find_character(&aliases.character(&command.channel, defender_query)) else {
+            let suggestions = suggest::characters(&ctx.data, defender_query);
+            return vec![describe_error(ParseFramesCommandError::UnknownCharacter { query: defender_query.clone(), suggestions }, usage)];
+        };
+
+        let attacker = format!("{} {}", query.character.name, query.found.input);
+        let onblock = FrameValue::parse(&query.found.onblock);
+        let Some(advantage) = onblock.frames.clone() else {
+            return vec![format!("{} has no frame advantage on block to punish ({})", attacker, onblock)];
+        };
+        // how long the defender has to hit back before the attacker can block again
+        let window = -advantage;
+        if window.max() <= 0 {
+            return vec![format!("{} is {} on block, nothing punishes it", attacker, onblock)];
+        }
+
+        let punishes = punishes(defender, &window);
+        if punishes.is_empty() {
+            return vec![format!("{} has nothing fast enough to punish {} ({} on block)", defender.name, attacker, onblock)];
+        }
+        let listed = punishes.iter()
+            .take(MAX_PUNISHES)
+            .map(|m| format!("{} ({}f, {} dmg)", m.input, m.startup, m.damage))
+            .collect::<Vec<String>>();
+        vec![format!("{} punishes {} ({} on block) with: {}", defender.name, attacker, onblock, listed.join(", "))]
+    }
+}
+
+// The defender's moves that are fast enough for `window`, most damage first and the faster move
+// when that's a tie
+fn punishes<'a>(defender: &'a Character, window: &Frames) -> Vec<&'a Move> {
+    let mut punishes = defender.moves.iter()
+        .filter(|m| from_blockstun(&m.input))
+        .filter_map(|m| Some((m, FrameValue::parse(&m.startup).frames?)))
+        .filter(|(_, startup)| matches!(startup.compare(window), Some(Ordering::Less | Ordering::Equal)))
+        .collect::<Vec<(&Move, Frames)>>();
+    punishes.sort_by_key(|(m, startup)| (std::cmp::Reverse(damage(m)), startup.max()));
+    punishes.into_iter().map(|(m, _)| m).collect()
+}
+
+// Blockstun ends on the ground and out of any stance, so air moves, Leo's backturn moves,
+// follow-ups and button releases can't be what hits back
+fn from_blockstun(input: &str) -> bool {
+    let input = input.trim().to_ascii_lowercase();
+    !(input.starts_with("j.") || input.starts_with("bt.") || input.starts_with(']') || input.contains('~'))
+}
+
+// moves without a readable damage value sort last
+fn damage(found: &Move) -> i32 {
+    FrameValue::parse(&found.damage).frames.map(|d| d.total()).unwrap_or(i32::MIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame_value::FrameValue;
+    use crate::framedata::{Character, Move};
+
+    use super::punishes;
+
+    fn attack(input: &str, startup: &str, damage: &str) -> Move {
+        Move::test(input).startup(startup).damage(damage)
+    }
+
+    fn inputs(window: &str, defender: &Character) -> Vec<String> {
+        let window = -FrameValue::parse(window).frames.unwrap();
+        punishes(defender, &window).iter().map(|m| m.input.clone()).collect()
+    }
+
+    #[test]
+    fn only_grounded_moves_fast_enough_by_damage() {
+        let ky = Character {
+            name: "Ky Kiske".to_string(),
+            moves: vec![
+                attack("5P", "4", "22"),
+                attack("c.S", "7", "30"),
+                attack("2D", "10", "38"),
+                attack("5H", "10", "42"),
+                attack("6H", "13", "50"),
+                attack("623S", "9", "48"),
+                attack("632146H", "7+1", "60"),
+                attack("j.H", "9", "40"),
+                attack("j.D", "10", "45"),
+                attack("bt.H", "8", "55"),
+                attack("236K~K", "5", "50"),
+                attack("]S[", "6", "50"),
+                attack("Taunt", "-", "-"),
+                attack("2K", "5", "?"),
+            ],
+        };
+
+        assert_eq!(inputs("-12", &ky), ["632146H", "623S", "5H", "2D", "c.S", "5P", "2K"]);
+        // a range only leaves its shortest window
+        assert_eq!(inputs("-9~-7", &ky), ["c.S", "5P", "2K"]);
+        assert!(inputs("-3", &ky).is_empty());
+    }
+}
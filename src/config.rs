@@ -0,0 +1,549 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+use tokio::sync::watch;
+use url::Url;
+
+use crate::cooldown;
+use crate::template::Template;
+
+pub const DEFAULT_CONFIG_PATH: &str = "dustloopbot.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default = "default_nick")]
+    pub nick: String,
+    #[serde(default)]
+    pub token: TokenSource,
+    #[serde(default = "default_server")]
+    pub server: String,
+    // plain ws sends the token in the clear, only meant for local test servers
+    #[serde(default)]
+    pub allow_insecure: bool,
+    #[serde(default = "default_capabilities")]
+    pub capabilities: Vec<String>,
+    #[serde(default = "default_command_prefix")]
+    pub command_prefix: String,
+    #[serde(default)]
+    pub channels: Vec<String>,
+    // per-channel overrides, keyed by channel name
+    #[serde(default)]
+    pub channel: HashMap<String, ChannelConfig>,
+    #[serde(default)]
+    pub data: DataConfig,
+    // keyed by the command's main name, e.g. [cooldowns.fd]
+    #[serde(default)]
+    pub cooldowns: HashMap<String, CooldownConfig>,
+    // identical frame data lookups in a channel within this many seconds are only answered once, 0 disables it
+    #[serde(default = "default_dedup_window_secs")]
+    pub dedup_window_secs: u64,
+    // answer as a threaded reply to the message that asked, needs the twitch.tv/tags capability
+    #[serde(default = "default_true")]
+    pub threaded_replies: bool,
+    // what to do with replies over Twitch's message length limit
+    #[serde(default)]
+    pub long_replies: LongReplies,
+    // how moves are formatted, see template.rs for the placeholders
+    #[serde(default)]
+    pub template: Template,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelConfig {
+    pub command_prefix: Option<String>,
+    // replaces the global cooldown of the same command in this channel
+    #[serde(default)]
+    pub cooldowns: HashMap<String, CooldownConfig>,
+    pub dedup_window_secs: Option<u64>,
+    pub threaded_replies: Option<bool>,
+    pub long_replies: Option<LongReplies>,
+    pub template: Option<Template>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LongReplies {
+    // send the rest in follow up messages
+    #[default]
+    Split,
+    // cut it off and mark it with an ellipsis
+    Truncate,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CooldownConfig {
+    // time between uses by anyone in the channel
+    #[serde(default)]
+    pub channel_secs: u64,
+    // time between uses by the same user in the channel
+    #[serde(default)]
+    pub user_secs: u64,
+    // moderators and the broadcaster skip the cooldown
+    #[serde(default = "default_true")]
+    pub exempt_mods: bool,
+    // reply with the time left instead of silently ignoring the command
+    #[serde(default)]
+    pub notify: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataConfig {
+    // an empty path disables the cache
+    #[serde(default = "default_cache")]
+    pub cache: String,
+    #[serde(default)]
+    pub offline: bool,
+    #[serde(default = "default_refresh_interval_secs")]
+    pub refresh_interval_secs: u64,
+    // community nicknames for characters and moves. Changes made in chat are saved next to it in
+    // <name>.local.toml, an empty path keeps them in memory only
+    #[serde(default = "default_aliases")]
+    pub aliases: String,
+}
+
+impl Default for DataConfig {
+    fn default() -> Self {
+        DataConfig {
+            cache: default_cache(),
+            offline: false,
+            refresh_interval_secs: default_refresh_interval_secs(),
+            aliases: default_aliases(),
+        }
+    }
+}
+
+// Where the oauth token is read from, e.g. `token = { env = "TWITCH_TOKEN" }`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum TokenSource {
+    Env(String),
+    File(PathBuf),
+    Value(String),
+}
+
+impl Default for TokenSource {
+    fn default() -> Self {
+        TokenSource::Env("TWITCH_TOKEN".to_string())
+    }
+}
+
+impl TokenSource {
+    pub fn resolve(&self) -> Result<String, ConfigError> {
+        let token = match self {
+            TokenSource::Env(var) => std::env::var(var)
+                .map_err(|_| ConfigError::at("token.env", format!("environment variable {} is not set", var)))?,
+            TokenSource::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| ConfigError::at("token.file", format!("could not read {}: {}", path.display(), e)))?,
+            TokenSource::Value(value) => value.clone(),
+        };
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(ConfigError::at("token", "token is empty"));
+        }
+        if token.starts_with("oauth:") {
+            Ok(token.to_string())
+        } else {
+            Ok(format!("oauth:{}", token))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    // the offending key, e.g. `channels[2]` or `channel.sagan37.command_prefix`
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn at(key: impl Into<String>, message: impl Into<String>) -> ConfigError {
+        ConfigError { key: Some(key.into()), message: message.into() }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "invalid config key `{}`: {}", key, self.message),
+            None => write!(f, "invalid config: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError { key: None, message: format!("could not read {}: {}", path.display(), e) })?;
+        Config::parse(&raw)
+    }
+
+    pub fn parse(raw: &str) -> Result<Config, ConfigError> {
+        // toml's own errors already name the key and line for type mismatches and unknown fields
+        let mut config: Config = toml::from_str(raw).map_err(|e| ConfigError { key: None, message: e.to_string() })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Checks what serde can't and lowercases channel names, since that's what Twitch uses in IRC
+    fn validate(&mut self) -> Result<(), ConfigError> {
+        if !is_valid_login(&self.nick) {
+            return Err(ConfigError::at("nick", format!("'{}' is not a valid Twitch login", self.nick)));
+        }
+        self.nick = self.nick.to_ascii_lowercase();
+
+        let url = Url::parse(&self.server).map_err(|e| ConfigError::at("server", e.to_string()))?;
+        match url.scheme() {
+            "wss" => {},
+            "ws" if self.allow_insecure => {},
+            "ws" => return Err(ConfigError::at("server", "refusing to connect without TLS, set allow_insecure = true to allow it")),
+            other => return Err(ConfigError::at("server", format!("unsupported scheme '{}'", other))),
+        }
+
+        for (i, capability) in self.capabilities.iter().enumerate() {
+            if capability.is_empty() || capability.contains(char::is_whitespace) {
+                return Err(ConfigError::at(format!("capabilities[{}]", i), format!("'{}' is not a valid capability", capability)));
+            }
+        }
+
+        validate_prefix("command_prefix", &self.command_prefix)?;
+
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            let name = channel.trim_start_matches('#');
+            if !is_valid_login(name) {
+                return Err(ConfigError::at(format!("channels[{}]", i), format!("'{}' is not a valid Twitch channel", channel)));
+            }
+            *channel = name.to_ascii_lowercase();
+        }
+        for (i, channel) in self.channels.iter().enumerate() {
+            if self.channels[..i].contains(channel) {
+                return Err(ConfigError::at(format!("channels[{}]", i), format!("'{}' is listed more than once", channel)));
+            }
+        }
+
+        for (command, settings) in &self.cooldowns {
+            validate_cooldown(&format!("cooldowns.{}", command), settings)?;
+        }
+
+        let mut overrides = HashMap::new();
+        for (name, settings) in self.channel.drain() {
+            let lower = name.to_ascii_lowercase();
+            if !self.channels.contains(&lower) {
+                return Err(ConfigError::at(format!("channel.{}", name), "channel is not listed in `channels`"));
+            }
+            if let Some(prefix) = &settings.command_prefix {
+                validate_prefix(&format!("channel.{}.command_prefix", name), prefix)?;
+            }
+            for (command, cooldown) in &settings.cooldowns {
+                validate_cooldown(&format!("channel.{}.cooldowns.{}", name, command), cooldown)?;
+            }
+            overrides.insert(lower, settings);
+        }
+        self.channel = overrides;
+
+        if self.data.refresh_interval_secs == 0 {
+            return Err(ConfigError::at("data.refresh_interval_secs", "must be greater than 0"));
+        }
+        if self.data.offline && self.data.cache.is_empty() {
+            return Err(ConfigError::at("data.cache", "offline mode needs a cache file"));
+        }
+
+        Ok(())
+    }
+
+    pub fn url(&self) -> Url {
+        // validated on load
+        Url::parse(&self.server).expect("server url was validated")
+    }
+
+    pub fn command_prefix(&self, channel: &str) -> &str {
+        self.channel.get(channel)
+            .and_then(|c| c.command_prefix.as_deref())
+            .unwrap_or(&self.command_prefix)
+    }
+
+    pub fn dedup_window(&self, channel: &str) -> Duration {
+        let secs = self.channel.get(channel)
+            .and_then(|c| c.dedup_window_secs)
+            .unwrap_or(self.dedup_window_secs);
+        Duration::from_secs(secs)
+    }
+
+    pub fn threaded_replies(&self, channel: &str) -> bool {
+        self.channel.get(channel)
+            .and_then(|c| c.threaded_replies)
+            .unwrap_or(self.threaded_replies)
+    }
+
+    pub fn long_replies(&self, channel: &str) -> LongReplies {
+        self.channel.get(channel)
+            .and_then(|c| c.long_replies)
+            .unwrap_or(self.long_replies)
+    }
+
+    pub fn template(&self, channel: &str) -> &Template {
+        self.channel.get(channel)
+            .and_then(|c| c.template.as_ref())
+            .unwrap_or(&self.template)
+    }
+
+    pub fn cooldown(&self, channel: &str, command: &str) -> Option<&CooldownConfig> {
+        self.channel.get(channel)
+            .and_then(|c| c.cooldowns.get(command))
+            .or_else(|| self.cooldowns.get(command))
+    }
+
+    pub fn aliases_path(&self) -> Option<PathBuf> {
+        if self.data.aliases.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(&self.data.aliases))
+        }
+    }
+
+    pub fn cache_path(&self) -> Option<PathBuf> {
+        if self.data.cache.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(&self.data.cache))
+        }
+    }
+}
+
+// The live config. Connections subscribe to it so that edits to the file apply without reconnecting.
+pub struct ConfigStore {
+    path: PathBuf,
+    current: watch::Sender<Arc<Config>>,
+}
+
+impl ConfigStore {
+    pub fn open(path: PathBuf) -> Result<Arc<ConfigStore>, ConfigError> {
+        let config = Config::load(&path)?;
+        let (current, _) = watch::channel(Arc::new(config));
+        Ok(Arc::new(ConfigStore { path, current }))
+    }
+
+    pub fn get(&self) -> Arc<Config> {
+        self.current.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.current.subscribe()
+    }
+
+    // A config that fails to load or validate is logged and the previous one is kept
+    pub fn reload(&self) -> Result<(), ConfigError> {
+        let updated = Config::load(&self.path)?;
+        let previous = self.get();
+        if previous.nick != updated.nick || previous.token != updated.token || previous.server != updated.server
+            || previous.allow_insecure != updated.allow_insecure || previous.capabilities != updated.capabilities {
+            println!("Connection settings changed, they will apply on the next reconnect");
+        }
+        if previous.data != updated.data {
+            println!("Changes to [data] only apply after a restart");
+        }
+        self.current.send_replace(Arc::new(updated));
+        Ok(())
+    }
+}
+
+// Reloads the config when the file's modification time changes, or on SIGHUP
+pub fn spawn_watch(store: Arc<ConfigStore>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_modified = modified(&store.path);
+        let mut poll = tokio::time::interval(WATCH_INTERVAL);
+        #[cfg(unix)]
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+        loop {
+            #[cfg(unix)]
+            let signalled = tokio::select! {
+                _ = poll.tick() => false,
+                Some(_) = async { hangup.as_mut()?.recv().await } => true,
+            };
+            #[cfg(not(unix))]
+            let signalled = {
+                poll.tick().await;
+                false
+            };
+
+            let now_modified = modified(&store.path);
+            if !signalled && now_modified == last_modified {
+                continue;
+            }
+            last_modified = now_modified;
+
+            match store.reload() {
+                Ok(()) => println!("Reloaded config from {}", store.path.display()),
+                Err(err) => eprintln!("Could not reload {}, keeping the previous config: {}", store.path.display(), err),
+            }
+        }
+    })
+}
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// `--config <path>` or `--config=<path>` wins over DUSTLOOPBOT_CONFIG, which wins over the default
+pub fn path_from_args() -> PathBuf {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            if let Some(path) = args.next() {
+                return PathBuf::from(path);
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return PathBuf::from(path);
+        }
+    }
+    std::env::var("DUSTLOOPBOT_CONFIG").map(PathBuf::from).unwrap_or_else(|_| PathBuf::from(DEFAULT_CONFIG_PATH))
+}
+
+fn validate_prefix(key: &str, prefix: &str) -> Result<(), ConfigError> {
+    if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+        return Err(ConfigError::at(key, format!("'{}' must be non-empty and contain no whitespace", prefix)));
+    }
+    Ok(())
+}
+
+fn validate_cooldown(key: &str, settings: &CooldownConfig) -> Result<(), ConfigError> {
+    for (field, secs) in [("channel_secs", settings.channel_secs), ("user_secs", settings.user_secs)] {
+        if secs > cooldown::MAX_COOLDOWN_SECS {
+            return Err(ConfigError::at(format!("{}.{}", key, field), format!("{} is longer than the maximum of {}", secs, cooldown::MAX_COOLDOWN_SECS)));
+        }
+    }
+    Ok(())
+}
+
+fn is_valid_login(name: &str) -> bool {
+    (1..=25).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn default_dedup_window_secs() -> u64 {
+    10
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_nick() -> String {
+    "dustloopbot".to_string()
+}
+
+fn default_server() -> String {
+    "wss://irc-ws.chat.twitch.tv:443".to_string()
+}
+
+fn default_capabilities() -> Vec<String> {
+    vec!["twitch.tv/tags".to_string(), "twitch.tv/commands".to_string()]
+}
+
+fn default_command_prefix() -> String {
+    "!".to_string()
+}
+
+fn default_cache() -> String {
+    crate::cache::DEFAULT_CACHE_PATH.to_string()
+}
+
+fn default_aliases() -> String {
+    crate::aliases::DEFAULT_ALIASES_PATH.to_string()
+}
+
+fn default_refresh_interval_secs() -> u64 {
+    6 * 60 * 60
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    // the key of the error `raw` is rejected with
+    fn rejected(raw: &str) -> Option<String> {
+        Config::parse(raw).expect_err(raw).key
+    }
+
+    #[test]
+    fn minimal_config_is_valid() {
+        let config = Config::parse(r##"channels = ["#Foo"]"##).unwrap();
+        assert_eq!(config.channels, vec!["foo"]);
+    }
+
+    #[test]
+    fn duplicate_channels() {
+        assert_eq!(rejected(r##"channels = ["foo", "#FOO"]"##).as_deref(), Some("channels[1]"));
+    }
+
+    #[test]
+    fn override_for_unlisted_channel() {
+        let raw = r#"
+            channels = ["foo"]
+            [channel.bar]
+            command_prefix = "?"
+        "#;
+        assert_eq!(rejected(raw).as_deref(), Some("channel.bar"));
+    }
+
+    #[test]
+    fn plain_websocket_needs_allow_insecure() {
+        assert_eq!(rejected(r#"server = "ws://localhost:8080""#).as_deref(), Some("server"));
+        assert!(Config::parse("server = \"ws://localhost:8080\"\nallow_insecure = true").is_ok());
+    }
+
+    #[test]
+    fn command_prefix() {
+        assert_eq!(rejected(r#"command_prefix = """#).as_deref(), Some("command_prefix"));
+        let raw = r#"
+            channels = ["foo"]
+            [channel.foo]
+            command_prefix = "! "
+        "#;
+        assert_eq!(rejected(raw).as_deref(), Some("channel.foo.command_prefix"));
+    }
+
+    #[test]
+    fn cooldown_maximum() {
+        let raw = r#"
+            [cooldowns.fd]
+            user_secs = 3601
+        "#;
+        assert_eq!(rejected(raw).as_deref(), Some("cooldowns.fd.user_secs"));
+        let raw = r#"
+            channels = ["foo"]
+            [channel.foo.cooldowns.fd]
+            channel_secs = 86400
+        "#;
+        assert_eq!(rejected(raw).as_deref(), Some("channel.foo.cooldowns.fd.channel_secs"));
+    }
+
+    #[test]
+    fn refresh_interval_of_zero() {
+        let raw = r#"
+            [data]
+            refresh_interval_secs = 0
+        "#;
+        assert_eq!(rejected(raw).as_deref(), Some("data.refresh_interval_secs"));
+    }
+
+    #[test]
+    fn offline_without_cache() {
+        let raw = r#"
+            [data]
+            offline = true
+            cache = ""
+        "#;
+        assert_eq!(rejected(raw).as_deref(), Some("data.cache"));
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config::CooldownConfig;
+
+// entries older than this are pruned, so no cooldown may be longer
+pub const MAX_COOLDOWN_SECS: u64 = 60 * 60;
+const FORGET_AFTER: Duration = Duration::from_secs(MAX_COOLDOWN_SECS);
+const PRUNE_ABOVE: usize = 1024;
+
+#[derive(Debug, Default)]
+pub struct Cooldowns {
+    // keyed by (channel, command)
+    channel: HashMap<(String, String), Instant>,
+    // keyed by (channel, command, user)
+    user: HashMap<(String, String, String), Instant>,
+    // when a user was last told a command is cooling down and for how long, keyed like `user`
+    notified: HashMap<(String, String, String), (Instant, Duration)>,
+}
+
+impl Cooldowns {
+    // Returns how much longer the command is cooling down for, or records the use and returns None
+    pub fn check(&mut self, settings: &CooldownConfig, channel: &str, command: &str, user: &str, now: Instant) -> Option<Duration> {
+        let channel_key = (channel.to_string(), command.to_string());
+        let user_key = (channel.to_string(), command.to_string(), user.to_string());
+
+        let channel_left = remaining(self.channel.get(&channel_key), settings.channel_secs, now);
+        let user_left = remaining(self.user.get(&user_key), settings.user_secs, now);
+        let left = channel_left.max(user_left);
+        if !left.is_zero() {
+            return Some(left);
+        }
+
+        self.prune(now);
+        self.channel.insert(channel_key, now);
+        self.user.insert(user_key, now);
+        None
+    }
+
+    // Whether to tell `user` about the cooldown, which happens once per cooldown so the notices
+    // themselves can't flood the channel
+    pub fn should_notify(&mut self, channel: &str, command: &str, user: &str, left: Duration, now: Instant) -> bool {
+        let key = (channel.to_string(), command.to_string(), user.to_string());
+        if let Some((noted, until)) = self.notified.get(&key) {
+            if !until.saturating_sub(now.saturating_duration_since(*noted)).is_zero() {
+                return false;
+            }
+        }
+        if self.notified.len() > PRUNE_ABOVE {
+            self.notified.retain(|_, (noted, until)| !until.saturating_sub(now.saturating_duration_since(*noted)).is_zero());
+        }
+        self.notified.insert(key, (now, left));
+        true
+    }
+
+    fn prune(&mut self, now: Instant) {
+        if self.channel.len() > PRUNE_ABOVE {
+            self.channel.retain(|_, used| now.saturating_duration_since(*used) < FORGET_AFTER);
+        }
+        if self.user.len() > PRUNE_ABOVE {
+            self.user.retain(|_, used| now.saturating_duration_since(*used) < FORGET_AFTER);
+        }
+    }
+}
+
+fn remaining(last_used: Option<&Instant>, secs: u64, now: Instant) -> Duration {
+    match last_used {
+        // subtracting from the cooldown can't overflow the way adding to an Instant can
+        Some(used) => Duration::from_secs(secs).saturating_sub(now.saturating_duration_since(*used)),
+        None => Duration::ZERO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::config::CooldownConfig;
+
+    use super::Cooldowns;
+
+    fn settings(channel_secs: u64, user_secs: u64) -> CooldownConfig {
+        CooldownConfig { channel_secs, user_secs, ..CooldownConfig::default() }
+    }
+
+    #[test]
+    fn channel_cooldown_applies_to_everyone() {
+        let mut cooldowns = Cooldowns::default();
+        let settings = settings(10, 0);
+        let now = Instant::now();
+        assert_eq!(cooldowns.check(&settings, "sagan37", "fd", "a", now), None);
+        assert_eq!(cooldowns.check(&settings, "sagan37", "fd", "b", now + Duration::from_secs(4)), Some(Duration::from_secs(6)));
+        // other channels and commands are separate
+        assert_eq!(cooldowns.check(&settings, "other", "fd", "b", now), None);
+        assert_eq!(cooldowns.check(&settings, "sagan37", "punish", "b", now), None);
+        assert_eq!(cooldowns.check(&settings, "sagan37", "fd", "b", now + Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn user_cooldown_applies_per_user() {
+        let mut cooldowns = Cooldowns::default();
+        let settings = settings(2, 30);
+        let now = Instant::now();
+        assert_eq!(cooldowns.check(&settings, "sagan37", "fd", "a", now), None);
+        assert_eq!(cooldowns.check(&settings, "sagan37", "fd", "b", now + Duration::from_secs(5)), None);
+        assert_eq!(cooldowns.check(&settings, "sagan37", "fd", "a", now + Duration::from_secs(10)), Some(Duration::from_secs(20)));
+        assert_eq!(cooldowns.check(&settings, "sagan37", "fd", "a", now + Duration::from_secs(30)), None);
+    }
+
+    #[test]
+    fn notifies_once_per_cooldown() {
+        let mut cooldowns = Cooldowns::default();
+        let now = Instant::now();
+        let left = Duration::from_secs(10);
+        assert!(cooldowns.should_notify("sagan37", "fd", "a", left, now));
+        assert!(!cooldowns.should_notify("sagan37", "fd", "a", left, now + Duration::from_secs(3)));
+        assert!(cooldowns.should_notify("sagan37", "fd", "b", left, now + Duration::from_secs(3)));
+        assert!(cooldowns.should_notify("sagan37", "fd", "a", left, now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn huge_cooldowns_dont_overflow() {
+        let settings = CooldownConfig { channel_secs: u64::MAX, user_secs: i64::MAX as u64, ..CooldownConfig::default() };
+        let mut cooldowns = Cooldowns::default();
+        let now = Instant::now();
+        assert_eq!(cooldowns.check(&settings, "sagan37", "fd", "viewer", now), None);
+        assert!(cooldowns.check(&settings, "sagan37", "fd", "viewer", now + Duration::from_secs(5)).is_some());
+    }
+}
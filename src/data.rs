@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::cache;
+use crate::frame_value::FrameValue;
+use crate::framedata::FrameData;
+use crate::reconnect::Backoff;
+
+// Holds the scraped frame data for the lifetime of the process so it survives reconnects.
+// Readers get a cheap snapshot that stays valid even if the data is replaced afterwards.
+pub struct DataStore {
+    current: RwLock<Arc<FrameData>>,
+    cache_path: Option<PathBuf>,
+    from_cache: bool,
+}
+
+pub struct DataOptions {
+    pub cache_path: Option<PathBuf>,
+    // only ever read the cache, never scrape dustloop
+    pub offline: bool,
+}
+
+impl DataStore {
+    // Starts from the cache file if there is one so the bot is usable right away. Without a cache
+    // this keeps retrying until dustloop has been scraped once, there's nothing to answer with before that.
+    pub async fn open(options: DataOptions) -> Result<Arc<DataStore>, Box<dyn Error>> {
+        if let Some(path) = &options.cache_path {
+            // a cache written by an older version or from a bad scrape is no better than none
+            let cached = cache::read(path).and_then(|cached| validate(&cached.data, None).map(|()| cached).map_err(Into::into));
+            match cached {
+                Ok(cached) => {
+                    println!("Loaded frame data from {} ({}s old)", path.display(), cached.age().as_secs());
+                    return Ok(Arc::new(DataStore {
+                        current: RwLock::new(Arc::new(cached.data)),
+                        cache_path: options.cache_path,
+                        from_cache: true,
+                    }));
+                },
+                Err(err) if options.offline => {
+                    return Err(format!("Offline mode needs a readable cache at {}: {}", path.display(), err).into());
+                },
+                Err(err) => println!("No usable frame data cache at {}: {}", path.display(), err),
+            }
+        } else if options.offline {
+            return Err("Offline mode needs a cache file".into());
+        }
+
+        let mut backoff = Backoff::default();
+        loop {
+            match fetch(None).await {
+                Ok(data) => {
+                    let store = DataStore {
+                        current: RwLock::new(Arc::new(data)),
+                        cache_path: options.cache_path,
+                        from_cache: false,
+                    };
+                    store.write_cache();
+                    return Ok(Arc::new(store));
+                },
+                Err(err) => {
+                    let delay = backoff.next_delay();
+                    eprintln!("{}. Retrying in {:.1}s", err, delay.as_secs_f64());
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+
+    // Scrapes dustloop again and swaps the new data in if it looks sane, otherwise the
+    // last good data is kept
+    pub async fn reload(&self) -> Result<(), Box<dyn Error>> {
+        let data = fetch(Some(&self.get())).await?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(data);
+        self.write_cache();
+        Ok(())
+    }
+
+    pub fn get(&self) -> Arc<FrameData> {
+        // a poisoned lock still holds the last good data
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn write_cache(&self) {
+        if let Some(path) = &self.cache_path {
+            if let Err(err) = cache::write(path, &self.get()) {
+                eprintln!("Could not write frame data cache to {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+pub fn spawn_refresh(store: Arc<DataStore>, every: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // the first tick completes immediately, which is only wanted if we started from a possibly old cache
+        if !store.from_cache {
+            interval.tick().await;
+        }
+        loop {
+            interval.tick().await;
+            match store.reload().await {
+                Ok(()) => println!("Refreshed frame data"),
+                Err(err) => eprintln!("Frame data refresh failed, keeping the previous data: {}", err),
+            }
+        }
+    })
+}
+
+// Lookups that any complete scrape has to answer. A page layout change on dustloop tends to
+// produce data that loads fine but is missing most moves, so this catches it before it's used.
+const SANITY_PROBES: [(&str, &str); 4] = [("sol", "5k"), ("ky", "5p"), ("may", "6p"), ("chipp", "2d")];
+
+async fn fetch(previous: Option<&FrameData>) -> Result<FrameData, Box<dyn Error>> {
+    let data = ggstdl::load().await.map_err(|e| format!("Could not load frame data: {:?}", e))?;
+    let data = FrameData::from_ggstdl(&data);
+    validate(&data, previous)?;
+    Ok(data)
+}
+
+fn validate(data: &FrameData, previous: Option<&FrameData>) -> Result<(), String> {
+    // a startup that doesn't read as frames means the columns got mixed up
+    let failed = SANITY_PROBES.iter()
+        .filter(|(character, query)| !data.find_move(character, query).map(|m| FrameValue::parse(&m.startup).frames.is_some()).unwrap_or(false))
+        .map(|(character, query)| format!("{} {}", character, query))
+        .collect::<Vec<String>>();
+    if !failed.is_empty() {
+        return Err(format!("Loaded frame data failed sanity checks, could not find or read the startup of: {}", failed.join(", ")));
+    }
+    if let Some(previous) = previous {
+        // losing a handful of moves happens when dustloop renames things, losing half doesn't
+        if data.move_count() * 2 < previous.move_count() {
+            return Err(format!("Loaded frame data only has {} moves, previously had {}", data.move_count(), previous.move_count()));
+        }
+    }
+    Ok(())
+}
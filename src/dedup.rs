@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const PRUNE_ABOVE: usize = 1024;
+
+// Remembers which moves were recently answered in each channel so that chat asking the same
+// question several times in a row only gets one answer
+#[derive(Debug, Default)]
+pub struct Dedup {
+    // keyed by (channel, character, move input)
+    answered: HashMap<(String, String, String), Instant>,
+}
+
+impl Dedup {
+    // true if this was already answered within `window`, otherwise records it as answered now
+    pub fn is_duplicate(&mut self, channel: &str, character: &str, input: &str, window: Duration, now: Instant) -> bool {
+        if window.is_zero() {
+            return false;
+        }
+        let key = (channel.to_string(), character.to_string(), input.to_string());
+        if let Some(answered) = self.answered.get(&key) {
+            if now.saturating_duration_since(*answered) < window {
+                return true;
+            }
+        }
+        if self.answered.len() > PRUNE_ABOVE {
+            self.answered.retain(|_, answered| now.saturating_duration_since(*answered) < window);
+        }
+        self.answered.insert(key, now);
+        false
+    }
+}
@@ -0,0 +1,338 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Neg, Sub};
+
+// A frame data column like "7", "3(5)3", "-12", "+2 [+4]" or "KD", parsed so it can be
+// compared and computed with. The original text is kept for showing it in chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameValue {
+    pub raw: String,
+    // None when there are no numbers or they couldn't be made sense of, e.g. "Until landing"
+    pub frames: Option<Frames>,
+    pub effects: Vec<Effect>,
+    // the bracketed value, e.g. the [+4] in "+2 [+4]", usually a charged or close range version
+    pub alternate: Option<Box<FrameValue>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frames {
+    // "7", "-12", "+2"
+    Single(i32),
+    // "9~13", "-6~-2", lowest first
+    Range(i32, i32),
+    // "3(5)3", "2,2,2", "10*3" or "13+3", in order with the gaps between hits
+    Sequence(Vec<Segment>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub frames: i32,
+    // the (5) in "3(5)3", frames where the move isn't active
+    pub gap: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    Knockdown, HardKnockdown, WallBounce, WallStick, GroundBounce, Crumple, Launch,
+}
+
+impl Effect {
+    fn from_word(word: &str) -> Option<Effect> {
+        Some(match word {
+            "kd" | "knockdown" | "skd" => Effect::Knockdown,
+            "hkd" | "hardknockdown" => Effect::HardKnockdown,
+            "wb" | "wallbounce" => Effect::WallBounce,
+            "ws" | "wallstick" | "wallsplat" => Effect::WallStick,
+            "gb" | "groundbounce" => Effect::GroundBounce,
+            "crumple" => Effect::Crumple,
+            "launch" | "launches" => Effect::Launch,
+            _ => return None,
+        })
+    }
+}
+
+impl FrameValue {
+    pub fn parse(raw: &str) -> FrameValue {
+        let text = raw.trim().replace('\u{2212}', "-");
+        let (main, alternate) = match (text.find('['), text.rfind(']')) {
+            (Some(open), Some(close)) if open < close => {
+                let outside = format!("{} {}", &text[..open], &text[close + 1..]);
+                (outside, Some(Box::new(FrameValue::parse(&text[open + 1..close]))))
+            },
+            _ => (text.clone(), None),
+        };
+
+        let mut effects = vec![];
+        let mut numbers = String::new();
+        let mut unknown = false;
+        let words = main.split_whitespace().map(|w| w.to_lowercase()).collect::<Vec<String>>();
+        let mut i = 0;
+        while i < words.len() {
+            // two word effects like "Wall Stick"
+            let pair = words.get(i + 1).and_then(|next| Effect::from_word(&format!("{}{}", words[i], next)));
+            if let Some(effect) = pair {
+                effects.push(effect);
+                i += 2;
+                continue;
+            }
+            match Effect::from_word(words[i].trim_matches(|c: char| !c.is_alphanumeric())) {
+                Some(effect) => effects.push(effect),
+                None if words[i].chars().any(|c| c.is_ascii_digit()) || words[i].chars().all(|c| "+-~,()*x".contains(c)) => {
+                    numbers.push_str(&words[i]);
+                },
+                None => unknown = true,
+            }
+            i += 1;
+        }
+
+        let frames = if unknown || numbers.is_empty() { None } else { parse_frames(&numbers) };
+        FrameValue { raw: raw.to_string(), frames, effects, alternate }
+    }
+}
+
+impl fmt::Display for FrameValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+// no move hits anywhere near this often, a bigger "N*M" is a typo
+const MAX_REPEAT: i32 = 100;
+
+fn parse_frames(text: &str) -> Option<Frames> {
+    if let Some((low, high)) = text.split_once('~') {
+        return Some(Frames::between(low.parse().ok()?, high.parse().ok()?));
+    }
+    if let Ok(n) = text.parse() {
+        return Some(Frames::Single(n));
+    }
+
+    let mut segments = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(inner) = rest.strip_prefix('(') {
+            let close = inner.find(')')?;
+            segments.push(Segment { frames: inner[..close].parse().ok()?, gap: true });
+            rest = &inner[close + 1..];
+            continue;
+        }
+        let (frames, remaining) = digits(rest)?;
+        rest = remaining;
+        // "10*3" is three hits of 10
+        let mut times = 1;
+        if let Some(after) = rest.strip_prefix('*').or_else(|| rest.strip_prefix('x')) {
+            let (n, remaining) = digits(after)?;
+            if !(1..=MAX_REPEAT).contains(&n) {
+                return None;
+            }
+            times = n;
+            rest = remaining;
+        }
+        for _ in 0..times {
+            segments.push(Segment { frames, gap: false });
+        }
+        if let Some(after) = rest.strip_prefix(',').or_else(|| rest.strip_prefix('+')) {
+            if after.is_empty() {
+                return None;
+            }
+            rest = after;
+        }
+    }
+    match segments.as_slice() {
+        [] => None,
+        [single] if !single.gap => Some(Frames::Single(single.frames)),
+        _ => Some(Frames::Sequence(segments)),
+    }
+}
+
+fn digits(text: &str) -> Option<(i32, &str)> {
+    let len = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let n = text[..len].parse().ok()?;
+    Some((n, &text[len..]))
+}
+
+// Ranges and sequences take part in arithmetic and comparisons by their bounds, a sequence's
+// bounds both being its total, e.g. 11 for "3(5)3" or 30 for "10*3".
+impl Frames {
+    pub fn between(a: i32, b: i32) -> Frames {
+        if a == b {
+            Frames::Single(a)
+        } else {
+            Frames::Range(a.min(b), a.max(b))
+        }
+    }
+
+    pub fn min(&self) -> i32 {
+        match self {
+            Frames::Single(n) => *n,
+            Frames::Range(low, _) => *low,
+            Frames::Sequence(_) => self.total(),
+        }
+    }
+
+    pub fn max(&self) -> i32 {
+        match self {
+            Frames::Single(n) => *n,
+            Frames::Range(_, high) => *high,
+            Frames::Sequence(_) => self.total(),
+        }
+    }
+
+    pub fn total(&self) -> i32 {
+        match self {
+            Frames::Sequence(segments) => segments.iter().fold(0, |total: i32, s| total.saturating_add(s.frames)),
+            _ => self.max(),
+        }
+    }
+
+    // Equal for the same single value, Less when no value of self is above any value of other
+    // and Greater the other way around. Overlapping ranges are unordered.
+    pub fn compare(&self, other: &Frames) -> Option<Ordering> {
+        let (low, high, other_low, other_high) = (self.min(), self.max(), other.min(), other.max());
+        if low == high && other_low == other_high && low == other_low {
+            Some(Ordering::Equal)
+        } else if high <= other_low {
+            Some(Ordering::Less)
+        } else if low >= other_high {
+            Some(Ordering::Greater)
+        } else {
+            None
+        }
+    }
+}
+
+impl Add for Frames {
+    type Output = Frames;
+
+    fn add(self, other: Frames) -> Frames {
+        Frames::between(self.min().saturating_add(other.min()), self.max().saturating_add(other.max()))
+    }
+}
+
+impl Sub for Frames {
+    type Output = Frames;
+
+    fn sub(self, other: Frames) -> Frames {
+        self + -other
+    }
+}
+
+impl Add<i32> for Frames {
+    type Output = Frames;
+
+    fn add(self, n: i32) -> Frames {
+        self + Frames::Single(n)
+    }
+}
+
+impl Sub<i32> for Frames {
+    type Output = Frames;
+
+    fn sub(self, n: i32) -> Frames {
+        self - Frames::Single(n)
+    }
+}
+
+impl Neg for Frames {
+    type Output = Frames;
+
+    fn neg(self) -> Frames {
+        Frames::between(self.max().saturating_neg(), self.min().saturating_neg())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{Effect, FrameValue, Frames, Segment};
+
+    fn frames(raw: &str) -> Option<Frames> {
+        FrameValue::parse(raw).frames
+    }
+
+    fn hits(frames: &[i32]) -> Vec<Segment> {
+        frames.iter().map(|&frames| Segment { frames, gap: false }).collect()
+    }
+
+    #[test]
+    fn single_values() {
+        assert_eq!(frames("7"), Some(Frames::Single(7)));
+        assert_eq!(frames("-12"), Some(Frames::Single(-12)));
+        assert_eq!(frames("+2"), Some(Frames::Single(2)));
+        assert_eq!(frames(" 10 "), Some(Frames::Single(10)));
+        assert_eq!(frames("\u{2212}5"), Some(Frames::Single(-5)));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(frames("9~13"), Some(Frames::Range(9, 13)));
+        assert_eq!(frames("-6~-2"), Some(Frames::Range(-6, -2)));
+        assert_eq!(frames("+1 ~ -3"), Some(Frames::Range(-3, 1)));
+        assert_eq!(frames("4~4"), Some(Frames::Single(4)));
+    }
+
+    #[test]
+    fn sequences() {
+        let active = frames("3(5)3").unwrap();
+        assert_eq!(active, Frames::Sequence(vec![
+            Segment { frames: 3, gap: false }, Segment { frames: 5, gap: true }, Segment { frames: 3, gap: false },
+        ]));
+        assert_eq!(active.total(), 11);
+
+        assert_eq!(frames("2,2,2"), Some(Frames::Sequence(hits(&[2, 2, 2]))));
+        assert_eq!(frames("10*3"), Some(Frames::Sequence(hits(&[10, 10, 10]))));
+        assert_eq!(frames("13+3").map(|f| f.total()), Some(16));
+    }
+
+    #[test]
+    fn alternates_and_effects() {
+        let onblock = FrameValue::parse("+2 [+4]");
+        assert_eq!(onblock.frames, Some(Frames::Single(2)));
+        assert_eq!(onblock.alternate.as_ref().and_then(|a| a.frames.clone()), Some(Frames::Single(4)));
+        assert_eq!(onblock.to_string(), "+2 [+4]");
+
+        let onhit = FrameValue::parse("KD");
+        assert_eq!(onhit.frames, None);
+        assert_eq!(onhit.effects, vec![Effect::Knockdown]);
+
+        let onhit = FrameValue::parse("+23 Wall Stick [HKD]");
+        assert_eq!(onhit.frames, Some(Frames::Single(23)));
+        assert_eq!(onhit.effects, vec![Effect::WallStick]);
+        assert_eq!(onhit.alternate.unwrap().effects, vec![Effect::HardKnockdown]);
+    }
+
+    #[test]
+    fn unparseable() {
+        for raw in ["", "-", "Until landing", "14 after landing", "3(5", "2,", "10*0", "1*101", "1*2147483647"] {
+            assert_eq!(frames(raw), None, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(-Frames::Single(-12), Frames::Single(12));
+        assert_eq!(-Frames::Range(-6, -2), Frames::Range(2, 6));
+        assert_eq!(Frames::Single(7) + 3, Frames::Single(10));
+        assert_eq!(Frames::Range(9, 13) - Frames::Single(2), Frames::Range(7, 11));
+        assert_eq!(frames("10*3").unwrap() + 5, Frames::Single(35));
+    }
+
+    #[test]
+    fn huge_values_saturate() {
+        assert_eq!(frames("2147483647,1").map(|f| f.total()), Some(i32::MAX));
+        assert_eq!(frames("2000000000*100").map(|f| f.total()), Some(i32::MAX));
+        assert_eq!(Frames::Single(i32::MAX) + 1, Frames::Single(i32::MAX));
+        assert_eq!(-Frames::Single(i32::MIN), Frames::Single(i32::MAX));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(Frames::Single(5).compare(&Frames::Single(7)), Some(Ordering::Less));
+        assert_eq!(Frames::Single(7).compare(&Frames::Single(7)), Some(Ordering::Equal));
+        assert_eq!(Frames::Range(9, 13).compare(&Frames::Single(7)), Some(Ordering::Greater));
+        assert_eq!(Frames::Range(9, 13).compare(&Frames::Single(10)), None);
+        // a 9 frame move still fits a window of at least 9
+        assert_eq!(Frames::Single(9).compare(&Frames::Range(9, 12)), Some(Ordering::Less));
+    }
+}
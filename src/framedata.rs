@@ -0,0 +1,251 @@
+use ggstdl::GGSTDLData;
+use serde::{Deserialize, Serialize};
+
+use crate::search;
+
+// Our own copy of the scraped data. Unlike GGSTDLData this can be written to and read back
+// from the on-disk cache, so the bot can answer without ever reaching dustloop.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FrameData {
+    pub characters: Vec<Character>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Character {
+    pub name: String,
+    pub moves: Vec<Move>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Move {
+    pub name: String,
+    pub input: String,
+    pub damage: String,
+    pub guard: String,
+    pub startup: String,
+    pub active: String,
+    pub recovery: String,
+    pub onblock: String,
+    pub onhit: String,
+    pub level: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupError {
+    UnknownCharacter, UnknownMove,
+}
+
+impl FrameData {
+    pub fn from_ggstdl(data: &GGSTDLData) -> FrameData {
+        let characters = data.characters.iter().map(|character| Character {
+            name: character.name.clone(),
+            moves: character.moves.iter().map(|m| Move {
+                name: m.name.clone(),
+                input: m.input.clone(),
+                damage: m.damage.clone(),
+                guard: m.guard.clone(),
+                startup: m.startup.clone(),
+                active: m.active.clone(),
+                recovery: m.recovery.clone(),
+                onblock: m.onblock.clone(),
+                onhit: m.onhit.clone(),
+                level: m.level.clone(),
+            }).collect(),
+        }).collect();
+        FrameData { characters }
+    }
+
+    pub fn move_count(&self) -> usize {
+        self.characters.iter().map(|c| c.moves.len()).sum()
+    }
+
+    // "sol" finds "Sol Badguy", "chaos" finds "Happy Chaos"
+    pub fn find_character(&self, query: &str) -> Option<&Character> {
+        let query = normalize(query);
+        if query.is_empty() {
+            return None;
+        }
+        self.characters.iter().find(|c| normalize(&c.name) == query)
+            .or_else(|| self.characters.iter().find(|c| c.name.split_whitespace().any(|word| normalize(word).starts_with(&query))))
+            .or_else(|| self.characters.iter().find(|c| normalize(&c.name).contains(&query)))
+    }
+
+    pub fn find_move(&self, character_query: &str, move_query: &str) -> Result<&Move, LookupError> {
+        self.find(character_query, move_query).map(|(_, found)| found)
+    }
+
+    // like find_move but also returns the character the move belongs to
+    pub fn find(&self, character_query: &str, move_query: &str) -> Result<(&Character, &Move), LookupError> {
+        let character = self.find_character(character_query).ok_or(LookupError::UnknownCharacter)?;
+        let found = character.find_move(move_query).ok_or(LookupError::UnknownMove)?;
+        Ok((character, found))
+    }
+}
+
+impl Character {
+    // the best match even if others match about as well, see search::find_move to tell those apart
+    pub fn find_move(&self, query: &str) -> Option<&Move> {
+        search::rank_moves(self, query).first().map(|c| c.found)
+    }
+
+    // only moves whose whole input or name is the query
+    pub fn find_move_exact(&self, query: &str) -> Option<&Move> {
+        let query = normalize(query);
+        self.moves.iter().find(|m| normalize(&m.input) == query)
+            .or_else(|| self.moves.iter().find(|m| normalize(&m.name) == query))
+    }
+}
+
+// builds moves for tests, named after their input and with the rest filled in plausibly
+#[cfg(test)]
+impl Move {
+    pub fn test(input: &str) -> Move {
+        Move {
+            name: input.to_string(),
+            input: input.to_string(),
+            damage: "30".to_string(),
+            guard: "All".to_string(),
+            startup: "10".to_string(),
+            active: "3".to_string(),
+            recovery: "20".to_string(),
+            onblock: "-5".to_string(),
+            onhit: "+1".to_string(),
+            level: "2".to_string(),
+        }
+    }
+
+    pub fn named(self, name: &str) -> Move {
+        Move { name: name.to_string(), ..self }
+    }
+
+    pub fn startup(self, startup: &str) -> Move {
+        Move { startup: startup.to_string(), ..self }
+    }
+
+    pub fn damage(self, damage: &str) -> Move {
+        Move { damage: damage.to_string(), ..self }
+    }
+}
+
+// A single frame data column, for answering things like "!fd sol 5k startup"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MoveField {
+    Damage, Guard, Startup, Active, Recovery, OnBlock, OnHit, Level,
+}
+
+impl MoveField {
+    // single letters are left out on purpose, they'd clash with buttons like in "2 s"
+    pub fn from_keyword(keyword: &str) -> Option<MoveField> {
+        Some(match keyword.to_ascii_lowercase().as_str() {
+            "damage" | "dmg" => MoveField::Damage,
+            "guard" | "grd" => MoveField::Guard,
+            "startup" | "start" | "su" => MoveField::Startup,
+            "active" | "act" => MoveField::Active,
+            "recovery" | "recov" | "rec" => MoveField::Recovery,
+            "onblock" | "block" | "blk" | "ob" => MoveField::OnBlock,
+            "onhit" | "hit" | "oh" => MoveField::OnHit,
+            "level" | "lvl" | "atklvl" => MoveField::Level,
+            _ => return None,
+        })
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MoveField::Damage => "damage",
+            MoveField::Guard => "guard",
+            MoveField::Startup => "startup",
+            MoveField::Active => "active",
+            MoveField::Recovery => "recovery",
+            MoveField::OnBlock => "on block",
+            MoveField::OnHit => "on hit",
+            MoveField::Level => "attack level",
+        }
+    }
+
+    pub fn value<'a>(&self, found: &'a Move) -> &'a str {
+        match self {
+            MoveField::Damage => &found.damage,
+            MoveField::Guard => &found.guard,
+            MoveField::Startup => &found.startup,
+            MoveField::Active => &found.active,
+            MoveField::Recovery => &found.recovery,
+            MoveField::OnBlock => &found.onblock,
+            MoveField::OnHit => &found.onhit,
+            MoveField::Level => &found.level,
+        }
+    }
+}
+
+// lowercase with everything but letters, digits and brackets removed so "j.D" matches "jd"
+pub fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '[' | ']'))
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, Character, FrameData, LookupError, Move};
+
+    fn data() -> FrameData {
+        FrameData {
+            characters: vec![
+                Character {
+                    name: "Sol Badguy".to_string(),
+                    moves: vec![
+                        Move::test("5K").named("5K"),
+                        Move::test("c.S").named("Close Slash"),
+                        Move::test("f.S").named("Far Slash"),
+                        Move::test("j.D").named("Jumping Dust"),
+                        Move::test("236P").named("Gun Flame"),
+                        Move::test("623S").named("Volcanic Viper"),
+                    ],
+                },
+                Character { name: "Happy Chaos".to_string(), moves: vec![Move::test("214P").named("Scapegoat")] },
+                Character { name: "Ky Kiske".to_string(), moves: vec![Move::test("236S").named("Stun Edge")] },
+            ],
+        }
+    }
+
+    #[test]
+    fn normalize_ignores_case_spacing_and_punctuation() {
+        assert_eq!(normalize("j.D"), "jd");
+        assert_eq!(normalize("Gun Flame"), "gunflame");
+        assert_eq!(normalize("[4]6S"), "[4]6s");
+    }
+
+    #[test]
+    fn characters_by_exact_name_word_prefix_or_substring() {
+        let data = data();
+        let name = |query| data.find_character(query).map(|c| c.name.as_str());
+        assert_eq!(name("sol badguy"), Some("Sol Badguy"));
+        assert_eq!(name("sol"), Some("Sol Badguy"));
+        assert_eq!(name("chaos"), Some("Happy Chaos"));
+        assert_eq!(name("iske"), Some("Ky Kiske"));
+        assert_eq!(name("potemkin"), None);
+        assert_eq!(name("..."), None);
+    }
+
+    #[test]
+    fn moves_by_input_or_name() {
+        let data = data();
+        let input = |character, query| data.find_move(character, query).map(|m| m.input.as_str());
+        assert_eq!(input("sol", "5k"), Ok("5K"));
+        assert_eq!(input("sol", "jd"), Ok("j.D"));
+        assert_eq!(input("sol", "cs"), Ok("c.S"));
+        assert_eq!(input("sol", "gun flame"), Ok("236P"));
+        assert_eq!(input("sol", "viper"), Ok("623S"));
+        assert_eq!(input("sol", "scapegoat"), Err(LookupError::UnknownMove));
+        assert_eq!(input("potemkin", "5k"), Err(LookupError::UnknownCharacter));
+    }
+
+    #[test]
+    fn exact_moves_only() {
+        let data = data();
+        let sol = data.find_character("sol").unwrap();
+        assert_eq!(sol.find_move_exact("C.S").map(|m| m.input.as_str()), Some("c.S"));
+        assert_eq!(sol.find_move_exact("volcanic viper").map(|m| m.input.as_str()), Some("623S"));
+        assert!(sol.find_move_exact("viper").is_none());
+    }
+}
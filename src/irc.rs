@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+// A single line of IRC as sent by Twitch, e.g.
+// @badges=moderator/1;display-name=Foo :foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :!fd sol 5k
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrcMessage {
+    pub tags: HashMap<String, String>,
+    pub prefix: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+impl IrcMessage {
+    pub fn parse(raw: &str) -> Option<IrcMessage> {
+        let mut rest = raw.trim_end_matches(['\r', '\n']);
+
+        let mut tags = HashMap::new();
+        if let Some(stripped) = rest.strip_prefix('@') {
+            let (raw_tags, remaining) = stripped.split_once(' ')?;
+            for tag in raw_tags.split(';').filter(|t| !t.is_empty()) {
+                let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+                tags.insert(key.to_string(), unescape_tag_value(value));
+            }
+            rest = remaining.trim_start_matches(' ');
+        }
+
+        let mut prefix = None;
+        if let Some(stripped) = rest.strip_prefix(':') {
+            let (raw_prefix, remaining) = stripped.split_once(' ')?;
+            prefix = Some(raw_prefix.to_string());
+            rest = remaining.trim_start_matches(' ');
+        }
+
+        let (command, mut rest) = match rest.split_once(' ') {
+            Some((command, remaining)) => (command, remaining),
+            None => (rest, ""),
+        };
+        if command.is_empty() {
+            return None;
+        }
+
+        // middle params are space separated, the trailing param starts with ':' and may contain anything
+        let mut params = vec![];
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing.to_string());
+                break;
+            }
+            match rest.split_once(' ') {
+                Some((param, remaining)) => {
+                    params.push(param.to_string());
+                    rest = remaining;
+                }
+                None => {
+                    params.push(rest.to_string());
+                    break;
+                }
+            }
+        }
+
+        Some(IrcMessage {
+            tags,
+            prefix,
+            command: command.to_ascii_uppercase(),
+            params,
+        })
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(|s| s.as_str()).filter(|s| !s.is_empty())
+    }
+
+    pub fn param(&self, index: usize) -> Option<&str> {
+        self.params.get(index).map(|s| s.as_str())
+    }
+
+    pub fn trailing(&self) -> Option<&str> {
+        self.params.last().map(|s| s.as_str())
+    }
+
+    // the nick part of a "nick!user@host" prefix
+    pub fn nick(&self) -> Option<&str> {
+        let prefix = self.prefix.as_deref()?;
+        Some(prefix.split_once('!').map(|(nick, _)| nick).unwrap_or(prefix))
+    }
+
+    // the channel a message targets, without the leading '#'
+    pub fn channel(&self) -> Option<&str> {
+        self.params.first()?.strip_prefix('#')
+    }
+
+    // badges are sent as "name/version,name/version"
+    pub fn badges(&self) -> Vec<(String, String)> {
+        parse_badges(self.tag("badges").unwrap_or(""))
+    }
+}
+
+pub fn parse_badges(raw: &str) -> Vec<(String, String)> {
+    raw.split(',')
+        .filter(|b| !b.is_empty())
+        .map(|b| {
+            let (name, version) = b.split_once('/').unwrap_or((b, ""));
+            (name.to_string(), version.to_string())
+        })
+        .collect()
+}
+
+// https://ircv3.net/specs/extensions/message-tags#escaping-values
+fn unescape_tag_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => out.push(';'),
+            Some('s') => out.push(' '),
+            Some('\\') => out.push('\\'),
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_badges, IrcMessage};
+
+    #[test]
+    fn privmsg_with_tags_and_prefix() {
+        let message = IrcMessage::parse("@badges=moderator/1,subscriber/12;display-name=Foo;id=abc \
+                                         :foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :!fd sol 5k\r\n").unwrap();
+        assert_eq!(message.command, "PRIVMSG");
+        assert_eq!(message.nick(), Some("foo"));
+        assert_eq!(message.channel(), Some("bar"));
+        assert_eq!(message.trailing(), Some("!fd sol 5k"));
+        assert_eq!(message.tag("display-name"), Some("Foo"));
+        assert_eq!(message.badges(), [("moderator".to_string(), "1".to_string()), ("subscriber".to_string(), "12".to_string())]);
+    }
+
+    #[test]
+    fn escaped_tag_values() {
+        let message = IrcMessage::parse(r"@msg=a\sb\:c\\d;end=x\ :tmi.twitch.tv NOTICE #bar :hi").unwrap();
+        assert_eq!(message.tag("msg"), Some(r"a b;c\d"));
+        // a lone trailing backslash is dropped
+        assert_eq!(message.tag("end"), Some("x"));
+    }
+
+    #[test]
+    fn tags_without_values() {
+        let message = IrcMessage::parse("@emote-only;slow= :tmi.twitch.tv ROOMSTATE #bar").unwrap();
+        assert_eq!(message.tags.get("emote-only").map(String::as_str), Some(""));
+        assert_eq!(message.tag("emote-only"), None);
+        assert_eq!(message.tag("slow"), None);
+    }
+
+    #[test]
+    fn no_prefix() {
+        let message = IrcMessage::parse("PING :tmi.twitch.tv").unwrap();
+        assert_eq!(message.prefix, None);
+        assert_eq!(message.command, "PING");
+        assert_eq!(message.trailing(), Some("tmi.twitch.tv"));
+        assert_eq!(message.nick(), None);
+    }
+
+    #[test]
+    fn trailing_keeps_colons_and_spaces() {
+        let message = IrcMessage::parse(":a!a@a PRIVMSG #bar :look :here  and : there").unwrap();
+        assert_eq!(message.params, ["#bar", "look :here  and : there"]);
+    }
+
+    #[test]
+    fn cap_ack() {
+        let message = IrcMessage::parse(":tmi.twitch.tv CAP * ACK :a b").unwrap();
+        assert_eq!(message.command, "CAP");
+        assert_eq!(message.param(0), Some("*"));
+        assert_eq!(message.param(1), Some("ACK"));
+        assert_eq!(message.trailing(), Some("a b"));
+    }
+
+    #[test]
+    fn incomplete_lines() {
+        for raw in ["", "\r\n", "@badges=moderator/1", "@badges=moderator/1 ", ":tmi.twitch.tv", ":tmi.twitch.tv "] {
+            assert_eq!(IrcMessage::parse(raw), None, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn badges() {
+        assert_eq!(parse_badges(""), []);
+        assert_eq!(parse_badges("vip/1,broadcaster"), [("vip".to_string(), "1".to_string()), ("broadcaster".to_string(), String::new())]);
+    }
+}
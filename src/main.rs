@@ -1,143 +1,347 @@
+use std::collections::HashSet;
 use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
 
+use futures_util::stream::SplitStream;
 use futures_util::{StreamExt, SinkExt};
-use ggstdl::{Move, GGSTDLData};
-use regex::Regex;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
-use url::Url;
+use capabilities::Capabilities;
+use commands::{parse_message_to_command, Context, Registry, State};
+use config::{Config, ConfigStore};
+use data::DataStore;
+use irc::IrcMessage;
+use outbound::Outbox;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite, tungstenite::Message, MaybeTlsStream, WebSocketStream};
 
-const TWITCH_IRC_ADDRESS: &str = "ws://irc-ws.chat.twitch.tv:80";
+mod aliases;
+mod cache;
+mod capabilities;
+mod commands;
+mod config;
+mod cooldown;
+mod data;
+mod dedup;
+mod frame_value;
+mod framedata;
+mod irc;
+mod notation;
+mod outbound;
+mod ratelimit;
+mod reconnect;
+mod search;
+mod suggest;
+mod template;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type WsReader = SplitStream<WsStream>;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let config_path = config::path_from_args();
+    let config = ConfigStore::open(config_path.clone()).map_err(|e| format!("{}: {}", config_path.display(), e))?;
+    config::spawn_watch(config.clone());
 
-    let pass = std::env::var("TWITCH_TOKEN")?;
-    let nick = "dustloopbot".to_string();
-    let channels = ["sagan37", "BedlessSleeper", "fgcsand", "me_lolo", "lapriz_", "kazam_slams"].map(|s| format!("#{}", s)).join(",");
-
-    let url = url::Url::parse(TWITCH_IRC_ADDRESS)?;
+    let current = config.get();
+    // fail early rather than on every reconnect
+    current.token.resolve()?;
+    if current.allow_insecure && current.url().scheme() == "ws" {
+        println!("Connecting to {} without TLS", current.url());
+    }
 
-    let mut val = web_socket_loop(&url, &pass, &nick, &channels).await;
-    while let Err(_) = val {
-        println!("Connection closed, resetting...");
-        val = web_socket_loop(&url, &pass, &nick, &channels).await;
+    let data = data::DataStore::open(data::DataOptions { cache_path: current.cache_path(), offline: current.data.offline }).await?;
+    if !current.data.offline {
+        data::spawn_refresh(data.clone(), Duration::from_secs(current.data.refresh_interval_secs));
     }
 
-    Ok(())
+    let registry = Registry::new();
+    let aliases = aliases::Aliases::load(current.aliases_path())?;
+    let state = Arc::new(State::new(aliases));
+
+    let outbox = Outbox::spawn();
+
+    reconnect::supervise(reconnect::Backoff::default(), || web_socket_loop(&config, &data, &registry, &state, &outbox)).await
 }
 
-async fn web_socket_loop(url: &Url, pass: &String, nick: &String, channels: &String) -> Result<(), Box<dyn Error>> {
-    let (mut ws_stream, _) = connect_async(url).await?;
-    
-    ws_stream.send(Message::Text(format!("PASS {}", pass))).await?;
-    ws_stream.send(Message::Text(format!("NICK {}", nick))).await?;
-    ws_stream.send(Message::Text(format!("JOIN {}", channels))).await?;
+// Opens a logged in connection that has joined every configured channel, returning the channels
+// joined and the capabilities the server acknowledged
+async fn connect(config: &Config) -> Result<(WsStream, Vec<String>, Arc<Capabilities>), Box<dyn Error>> {
+    let (mut ws_stream, _) = connect_async(config.url()).await?;
 
-    let data = ggstdl::load().await.expect("Could not load DustloopInfo");
+    let pass = config.token.resolve()?;
+    let acknowledged = capabilities::negotiate(&mut ws_stream, &config.capabilities, &pass, &config.nick).await?;
+    println!("Acknowledged capabilities: {:?}", acknowledged);
+    if !acknowledged.has(capabilities::TAGS) {
+        println!("{} not acknowledged, moderator checks and threaded replies are off", capabilities::TAGS);
+    }
 
-    while let Some(msg) = ws_stream.next().await {
-        let msg = msg?;
-        if let Ok(text) = msg.to_text() {
-            println!("{}", text);
+    if !config.channels.is_empty() {
+        ws_stream.send(Message::Text(format!("JOIN {}", channel_list(&config.channels)))).await?;
+    }
+    Ok((ws_stream, config.channels.clone(), Arc::new(acknowledged)))
+}
 
-            if text.starts_with("PING") {
-                let msg = text.splitn(2, " ").skip(1).next().unwrap();
-                ws_stream.send(Message::Text(format!("PONG {}", msg))).await?;
-                continue;
-            }
+// Joins and parts only the channels that differ between what is joined and what is configured
+fn sync_channels(outbox: &Outbox, joined: &[String], configured: &[String]) {
+    let to_join = configured.iter().filter(|c| !joined.contains(c)).cloned().collect::<Vec<String>>();
+    let to_part = joined.iter().filter(|c| !configured.contains(c)).cloned().collect::<Vec<String>>();
+    if !to_join.is_empty() {
+        println!("Joining {}", to_join.join(", "));
+        outbox.raw(format!("JOIN {}", channel_list(&to_join)));
+    }
+    if !to_part.is_empty() {
+        println!("Leaving {}", to_part.join(", "));
+        outbox.raw(format!("PART {}", channel_list(&to_part)));
+    }
+}
 
-            if let Some(command) = parse_message_to_command(text) {
-                println!("{:?}", command);
-                if command.command.eq_ignore_ascii_case("!fd") {
-                    match parse_frames_command(command.args, &data) {
-                        Ok(move_found) => {
-                            let move_print = format_move(move_found);
-                            ws_stream.send(format_msg(move_print, command.channel)).await?
-                        },
-                        Err(err) => {
-                            match err {
-                                ParseFramesCommandError::UnknownCharacter(query) => {
-                                    ws_stream.send(format_msg(format!("Currently unknown character: '{}'", query), command.channel)).await?;
-                                },
-                                ParseFramesCommandError::UnknownMove(query) => {
-                                    ws_stream.send(format_msg(format!("Currently unknown move: '{}'", query), command.channel)).await?;
-                                },
-                                ParseFramesCommandError::WrongArguments => {
-                                    ws_stream.send(format_msg("Invalid args, try: !frames <char> <move_query>".to_string(), command.channel)).await?;
-                                },
-                            }
-                        }
-                    }
-                }
+// USERSTATE tells us whether we're a moderator or VIP in a channel, ROOMSTATE whether it's in slow mode
+fn update_rate_limits(message: &IrcMessage, outbox: &Outbox) {
+    let Some(channel) = message.channel() else {
+        return;
+    };
+    match message.command.as_str() {
+        "USERSTATE" => {
+            let privileged = message.badges().iter().any(|(badge, _)| ratelimit::is_privileged_badge(badge));
+            outbox.set_privileged(channel, privileged);
+        },
+        "ROOMSTATE" => {
+            // ROOMSTATE updates only carry the tags that changed
+            if let Some(slow) = message.tags.get("slow").and_then(|s| s.parse().ok()) {
+                outbox.set_slow_mode(channel, Duration::from_secs(slow));
             }
-        }
+        },
+        _ => {},
     }
-    ws_stream.close(None).await?;
-    Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct Command {
-    pub channel: String,
-    pub command: String,
-    pub args: Vec<String>
+fn channel_list(channels: &[String]) -> String {
+    channels.iter().map(|s| format!("#{}", s)).collect::<Vec<String>>().join(",")
+}
+
+// how long a new connection gets to confirm its joins before we move over to it anyway
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A connection opened because of a RECONNECT that is still joining its channels. The old
+// connection keeps being read until it has, so nothing said in between is missed.
+struct Handover {
+    sink: outbound::WsSink,
+    reader: WsReader,
+    joined: Vec<String>,
+    capabilities: Arc<Capabilities>,
+    // channels the new connection hasn't confirmed with a JOIN or ROOMSTATE yet
+    unconfirmed: HashSet<String>,
+    // what arrived on the new connection meanwhile, replayed if the old one didn't deliver it
+    buffered: Vec<IrcMessage>,
+    // ids of the messages already handled from the old connection
+    handled: HashSet<String>,
+    deadline: tokio::time::Instant,
 }
 
-#[derive(Debug, Clone)]
-enum ParseFramesCommandError {
-    UnknownCharacter(String), UnknownMove(String), WrongArguments,
+enum Read {
+    Old(Option<Result<Message, tungstenite::Error>>),
+    New(Option<Result<Message, tungstenite::Error>>),
+    HandoverTimeout,
 }
 
-fn parse_frames_command<'a>(args: Vec<String>, data: &'a GGSTDLData) -> Result<&'a Move, ParseFramesCommandError> {
-    let mut iter = args.into_iter();
+async fn web_socket_loop(config: &ConfigStore, data: &Arc<DataStore>, registry: &Registry, state: &Arc<State>, outbox: &Outbox) -> Result<(), Box<dyn Error>> {
+    let mut config_updates = config.subscribe();
+    let (ws_stream, mut joined, mut capabilities) = connect(&config.get()).await?;
+    let (sink, mut reader) = ws_stream.split();
+    outbox.attach(sink, capabilities.clone());
+    let mut handover: Option<Handover> = None;
 
-    let character_query = iter.next().ok_or(ParseFramesCommandError::WrongArguments)?;
+    loop {
+        let deadline = handover.as_ref().map(|h| h.deadline);
+        let read = tokio::select! {
+            msg = reader.next() => Read::Old(msg),
+            msg = next_on_new(&mut handover) => Read::New(msg),
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if deadline.is_some() => Read::HandoverTimeout,
+            Ok(()) = config_updates.changed() => {
+                let updated = config_updates.borrow_and_update().clone();
+                sync_channels(outbox, &joined, &updated.channels);
+                joined = updated.channels.clone();
+                continue;
+            },
+        };
 
-    let move_query = iter.collect::<Vec<String>>().join(" ");
-    if move_query.is_empty() {
-        return Err(ParseFramesCommandError::WrongArguments);
-    }
+        match read {
+            Read::Old(msg) => {
+                let messages = match msg.map(read_lines) {
+                    Some(Ok(messages)) => messages,
+                    // the old connection going away mid handover is expected, finish moving over
+                    end if handover.is_some() => {
+                        if let Some(Err(err)) = end {
+                            println!("Old connection ended during reconnect: {}", err);
+                        }
+                        let new = handover.take().unwrap();
+                        (reader, joined, capabilities) = finish_handover(new, config, data, registry, state, outbox);
+                        continue;
+                    },
+                    Some(Err(err)) => return Err(err),
+                    None => break,
+                };
+                for message in messages {
+                    if let Some(reason) = capabilities::login_failure(&message) {
+                        return Err(Box::new(reconnect::FatalError(format!("Login rejected: {}", reason))));
+                    }
+
+                    // Twitch is about to restart the server we're on, so move over to a fresh connection
+                    // before this one goes away to avoid missing any commands
+                    if message.command == "RECONNECT" {
+                        if handover.is_some() {
+                            continue;
+                        }
+                        println!("Server requested a reconnect, opening a new connection");
+                        match start_handover(&config.get()).await {
+                            Ok(new) if new.unconfirmed.is_empty() => {
+                                (reader, joined, capabilities) = finish_handover(new, config, data, registry, state, outbox);
+                            },
+                            Ok(new) => handover = Some(new),
+                            // keep the old connection for as long as it lasts, the supervisor takes over after
+                            Err(err) => eprintln!("Could not open a new connection: {}", err),
+                        }
+                        continue;
+                    }
 
-    match data.find_move(&character_query, &move_query) {
-        Ok(move_found) => Ok(move_found),
-        Err(e) => Err(match e {
-            ggstdl::GGSTDLError::UnknownCharacter => ParseFramesCommandError::UnknownCharacter(character_query),
-            ggstdl::GGSTDLError::UnknownMove => ParseFramesCommandError::UnknownMove(move_query),
-        }),
+                    if message.command == "PING" {
+                        let payload = message.trailing().unwrap_or("tmi.twitch.tv");
+                        outbox.raw(format!("PONG :{}", payload));
+                        continue;
+                    }
+
+                    if let (Some(new), Some(id)) = (handover.as_mut(), message.tag("id")) {
+                        new.handled.insert(id.to_string());
+                    }
+                    handle_message(&message, &capabilities, config, data, registry, state, outbox);
+                }
+            },
+            Read::New(msg) => {
+                let Some(new) = handover.as_mut() else {
+                    continue;
+                };
+                let messages = match msg.map(read_lines) {
+                    Some(Ok(messages)) => messages,
+                    end => {
+                        match end {
+                            Some(Err(err)) => println!("New connection failed before taking over: {}", err),
+                            _ => println!("New connection closed before taking over"),
+                        }
+                        handover = None;
+                        continue;
+                    },
+                };
+                let mut failed = false;
+                for message in messages {
+                    if message.command == "PING" {
+                        let payload = message.trailing().unwrap_or("tmi.twitch.tv");
+                        // the old connection is still fine, so only the handover is given up on
+                        if let Err(err) = new.sink.send(Message::Text(format!("PONG :{}", payload))).await {
+                            println!("New connection failed before taking over: {}", err);
+                            failed = true;
+                            break;
+                        }
+                        continue;
+                    }
+                    let ours = message.nick().is_some_and(|nick| nick.eq_ignore_ascii_case(&config.get().nick));
+                    if (message.command == "JOIN" && ours) || message.command == "ROOMSTATE" {
+                        if let Some(channel) = message.channel() {
+                            new.unconfirmed.remove(channel);
+                        }
+                    }
+                    new.buffered.push(message);
+                }
+                if failed {
+                    handover = None;
+                } else if new.unconfirmed.is_empty() {
+                    let new = handover.take().unwrap();
+                    (reader, joined, capabilities) = finish_handover(new, config, data, registry, state, outbox);
+                }
+            },
+            Read::HandoverTimeout => {
+                if let Some(new) = handover.take() {
+                    println!("New connection didn't confirm joining {:?} in time, moving over anyway", new.unconfirmed);
+                    (reader, joined, capabilities) = finish_handover(new, config, data, registry, state, outbox);
+                }
+            },
+        }
     }
+    Ok(())
 }
 
-fn parse_message_to_command(raw: &str) -> Option<Command> {
-    // ensure it only gets evaluated once
-    lazy_static::lazy_static! {
-        static ref MATCH: Regex = Regex::new(r"PRIVMSG #(.*) :(.*)").expect("Could not load command pasing regex");
+async fn next_on_new(handover: &mut Option<Handover>) -> Option<Result<Message, tungstenite::Error>> {
+    match handover {
+        Some(new) => new.reader.next().await,
+        None => std::future::pending().await,
     }
+}
 
-    let caps = MATCH.captures(raw)?;
-    let channel = caps.get(1).map(|c| c.as_str())?.to_string();
-    let msg = caps.get(2).map(|c| c.as_str())?;
-    if msg.starts_with("!") {
-        let mut split = msg.splitn(2, " ");
-        let root = split.next()?.trim_end_matches("\r").to_string(); // if no args then this is here
-        let args = match split.next() {
-            Some(next) => next.trim_end_matches("\r").split(" ").map(|s| s.to_string()).collect::<Vec<String>>(),
-            None => vec![]
-        };
-        return Some(Command {
-            channel,
-            command: root,
-            args,
-        });
-    }
+async fn start_handover(config: &Config) -> Result<Handover, Box<dyn Error>> {
+    let (new_stream, joined, capabilities) = connect(config).await?;
+    let (sink, reader) = new_stream.split();
+    Ok(Handover {
+        sink,
+        reader,
+        unconfirmed: joined.iter().cloned().collect(),
+        joined,
+        capabilities,
+        buffered: vec![],
+        handled: HashSet::new(),
+        deadline: tokio::time::Instant::now() + HANDOVER_TIMEOUT,
+    })
+}
 
-    None
+// Moves the writer over to the new connection and handles whatever only it delivered.
+// Returns the reader, channels and capabilities to carry on with.
+fn finish_handover(new: Handover, config: &ConfigStore, data: &Arc<DataStore>, registry: &Registry, state: &Arc<State>, outbox: &Outbox) -> (WsReader, Vec<String>, Arc<Capabilities>) {
+    println!("Moved over to the new connection");
+    // attaching closes the old connection once the writer has moved over
+    outbox.attach(new.sink, new.capabilities.clone());
+    for message in new.buffered {
+        let seen = message.tag("id").is_some_and(|id| new.handled.contains(id));
+        // chat without an id can't be told apart from what the old connection delivered
+        if seen || (message.command == "PRIVMSG" && message.tag("id").is_none()) {
+            continue;
+        }
+        handle_message(&message, &new.capabilities, config, data, registry, state, outbox);
+    }
+    // the channels may have changed in the config while the new connection was joining
+    let configured = config.get().channels.clone();
+    sync_channels(outbox, &new.joined, &configured);
+    (new.reader, configured, new.capabilities)
 }
 
-fn format_msg(text: String, channel: String) -> Message {
-    Message::Text(format!("PRIVMSG #{} :{}", channel, text))
+// Fails on a close frame, otherwise the irc lines in a frame, which can carry several
+fn read_lines(msg: Result<Message, tungstenite::Error>) -> Result<Vec<IrcMessage>, Box<dyn Error>> {
+    let msg = msg?;
+    if let Message::Close(frame) = msg {
+        return Err(format!("Server closed the connection: {:?}", frame).into());
+    }
+    let Ok(text) = msg.to_text() else {
+        return Ok(vec![]);
+    };
+    Ok(text.lines()
+        .filter(|l| !l.is_empty())
+        .filter_map(|line| {
+            println!("{}", line);
+            IrcMessage::parse(line)
+        })
+        .collect())
 }
 
-fn format_move(fmt: &Move) -> String {
-    format!("{}: dmg=({}) guard=({}) startup=({}) active=({}) recov=({}) block=({}) hit=({}) atklvl=({})", 
-        fmt.input, fmt.damage, fmt.guard, fmt.startup, fmt.active, fmt.recovery, fmt.onblock, fmt.onhit, fmt.level)
+fn handle_message(message: &IrcMessage, capabilities: &Arc<Capabilities>, config: &ConfigStore, data: &Arc<DataStore>, registry: &Registry, state: &Arc<State>, outbox: &Outbox) {
+    update_rate_limits(message, outbox);
+
+    let ctx = Context { capabilities: capabilities.clone(), data: data.get(), config: config.get(), state: state.clone() };
+    if let Some(command) = parse_message_to_command(message, &ctx.config) {
+        println!("{:?}", command);
+        let reply_to = command.message_id.clone()
+            .filter(|_| ctx.capabilities.has(capabilities::TAGS) && ctx.config.threaded_replies(&command.channel));
+        let long_replies = ctx.config.long_replies(&command.channel);
+        for reply in registry.dispatch(&command, &ctx) {
+            for part in outbound::fit_message(&reply, outbound::MESSAGE_LIMIT, long_replies) {
+                outbox.say(&command.channel, part, reply_to.clone());
+            }
+        }
+    }
 }
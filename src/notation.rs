@@ -0,0 +1,389 @@
+// Rewrites the ways chat writes inputs, like "qcf+p", "236 P", "jD" or "cs", into the way
+// Dustloop lists them ("236P", "j.D", "c.S"). A query that isn't notation as a whole, e.g. a
+// move name, is returned as it was.
+pub fn canonical(query: &str) -> String {
+    parse(query).unwrap_or_else(|| query.to_string())
+}
+
+// longest first where one is the start of another
+const MOTIONS: &[(&str, &str)] = &[
+    ("qcf", "236"), ("qcb", "214"), ("hcbf", "632146"), ("hcf", "41236"), ("hcb", "63214"),
+    ("rdp", "421"), ("dp", "623"), ("srk", "623"), ("360", "632146"), ("spd", "632146"),
+];
+
+const BUTTONS: &[(&str, &str)] = &[
+    ("hs", "H"), ("p", "P"), ("k", "K"), ("s", "S"), ("h", "H"), ("d", "D"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prefix {
+    // j.D, j.236K
+    Jump,
+    // Leo's backturn stance, bt.P
+    Backturn,
+    // close and far slash, the only normals Strive tells apart by distance
+    Close, Far,
+    // old school cr.K and st.K
+    Crouching, Standing,
+}
+
+const PREFIXES: &[(&str, Prefix)] = &[
+    ("j.", Prefix::Jump), ("j", Prefix::Jump),
+    ("bt.", Prefix::Backturn), ("bt", Prefix::Backturn),
+    ("cl.", Prefix::Close), ("cl", Prefix::Close),
+    ("cr.", Prefix::Crouching), ("cr", Prefix::Crouching),
+    ("c.", Prefix::Close), ("c", Prefix::Close),
+    ("f.", Prefix::Far), ("f", Prefix::Far),
+    ("st.", Prefix::Standing), ("st", Prefix::Standing),
+];
+
+struct Input {
+    text: String,
+    // just a button, which only makes sense alone or as a follow-up
+    bare: bool,
+}
+
+fn parse(query: &str) -> Option<String> {
+    let cleaned = query.chars()
+        .filter(|c| !c.is_whitespace() && *c != '+')
+        .flat_map(|c| c.to_lowercase())
+        .collect::<String>();
+    let mut rest = cleaned.as_str();
+
+    let mut out = String::new();
+    let mut count = 0;
+    let mut followup = false;
+    let mut stray_button = false;
+    while !rest.is_empty() {
+        let next = if followup { direction(&mut rest).or_else(|| input(&mut rest)) } else { input(&mut rest) }?;
+        if count > 0 && !followup {
+            stray_button |= next.bare;
+            out.push(' ');
+        }
+        stray_button |= next.bare && count == 0 && !rest.is_empty() && !rest.starts_with('~');
+        out.push_str(&next.text);
+        count += 1;
+        followup = eat(&mut rest, "~");
+        if followup {
+            out.push('~');
+        }
+    }
+    // "ks" is more likely a word than two buttons
+    if count == 0 || followup || stray_button {
+        return None;
+    }
+    Some(out)
+}
+
+fn input(rest: &mut &str) -> Option<Input> {
+    // ]S[ is letting go of a held button, e.g. Leo's Brynhildr releases
+    if eat(rest, "]") {
+        let released = button(rest)?;
+        return eat(rest, "[").then(|| Input { text: format!("]{}[", released), bare: false });
+    }
+
+    let prefix = PREFIXES.iter().find_map(|(text, prefix)| eat(rest, text).then_some(*prefix));
+    match prefix {
+        Some(Prefix::Close) | Some(Prefix::Far) => {
+            let pressed = button(rest).filter(|b| *b == "S")?;
+            let text = if prefix == Some(Prefix::Close) { "c." } else { "f." };
+            Some(Input { text: format!("{}{}", text, repeated(rest, pressed)), bare: false })
+        },
+        Some(Prefix::Crouching) => Some(Input { text: format!("2{}", button(rest)?), bare: false }),
+        // there is no 5S, standing slash is either c.S or f.S
+        Some(Prefix::Standing) => Some(Input { text: format!("5{}", button(rest).filter(|b| *b != "S")?), bare: false }),
+        Some(Prefix::Jump) => Some(Input { text: format!("j.{}", body(rest)?.text), bare: false }),
+        Some(Prefix::Backturn) => Some(Input { text: format!("bt.{}", body(rest)?.text), bare: false }),
+        None => body(rest),
+    }
+}
+
+// [4]6S, 236236H, 5[D] and the like
+fn body(rest: &mut &str) -> Option<Input> {
+    let mut text = String::new();
+    if let Some(charged) = charge(rest) {
+        text.push_str(&format!("[{}]", charged));
+    }
+    while let Some(motion) = motion(rest) {
+        text.push_str(&motion);
+    }
+    let directions = !text.is_empty();
+    if eat(rest, "[") {
+        let held = button(rest)?;
+        if !eat(rest, "]") {
+            return None;
+        }
+        text.push_str(&format!("[{}]", held));
+    } else {
+        let pressed = button(rest)?;
+        if !directions {
+            return Some(Input { text: pressed.to_string(), bare: true });
+        }
+        // target combos like 6HH only follow normals, a single direction
+        if text.len() == 1 {
+            text.push_str(&repeated(rest, pressed));
+        } else {
+            text.push_str(pressed);
+        }
+    }
+    Some(Input { text, bare: false })
+}
+
+// a button pressed again right away, like the second S in Nagoriyuki's f.SS
+fn repeated(rest: &mut &str, pressed: &'static str) -> String {
+    let mut text = pressed.to_string();
+    loop {
+        let mut probe = *rest;
+        if button(&mut probe) != Some(pressed) {
+            return text;
+        }
+        *rest = probe;
+        text.push_str(pressed);
+    }
+}
+
+// a follow-up that is only a direction, like the 8 in 22S~8
+fn direction(rest: &mut &str) -> Option<Input> {
+    let len = rest.find(|c: char| !matches!(c, '1'..='9')).unwrap_or(rest.len());
+    if len == 0 || !(rest[len..].is_empty() || rest[len..].starts_with('~')) {
+        return None;
+    }
+    let (digits, remaining) = rest.split_at(len);
+    *rest = remaining;
+    Some(Input { text: digits.to_string(), bare: false })
+}
+
+// a held direction like the [4] in [4]6S
+fn charge(rest: &mut &str) -> Option<char> {
+    let mut chars = rest.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('['), Some(d @ '1'..='9'), Some(']')) => {
+            *rest = &rest[3..];
+            Some(d)
+        },
+        _ => None,
+    }
+}
+
+fn motion(rest: &mut &str) -> Option<String> {
+    if let Some(numpad) = MOTIONS.iter().find_map(|(name, numpad)| eat(rest, name).then_some(*numpad)) {
+        return Some(numpad.to_string());
+    }
+    let len = rest.find(|c: char| !matches!(c, '1'..='9')).unwrap_or(rest.len());
+    if len == 0 {
+        return None;
+    }
+    let (digits, remaining) = rest.split_at(len);
+    *rest = remaining;
+    Some(digits.to_string())
+}
+
+fn button(rest: &mut &str) -> Option<&'static str> {
+    BUTTONS.iter().find_map(|(name, canonical)| eat(rest, name).then_some(*canonical))
+}
+
+fn eat(rest: &mut &str, token: &str) -> bool {
+    match rest.strip_prefix(token) {
+        Some(remaining) => {
+            *rest = remaining;
+            true
+        },
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::canonical;
+
+    fn assert_all(expected: &str, queries: &[&str]) {
+        for query in queries {
+            assert_eq!(canonical(query), expected, "canonical({:?})", query);
+        }
+    }
+
+    #[test]
+    fn numpad_spacing_and_case() {
+        assert_all("236P", &["236P", "236p", "236 P", "236+p", "2 3 6 p"]);
+        assert_all("2D", &["2d", "2D", "2 d"]);
+        assert_all("5K", &["5k", "5 K", "st.k", "stk"]);
+        assert_all("2K", &["2k", "cr.k", "crk", "cr. K"]);
+    }
+
+    #[test]
+    fn motion_names() {
+        assert_all("236P", &["qcf+p", "qcfp", "QCF P"]);
+        assert_all("214K", &["qcb+k", "qcbk"]);
+        assert_all("623H", &["dp+h", "dph", "srk hs", "623hs"]);
+        assert_all("421S", &["rdp s", "rdps"]);
+        assert_all("41236H", &["hcf h", "hcf+hs"]);
+        assert_all("63214S", &["hcb s"]);
+        assert_all("236236H", &["qcf qcf h", "qcfqcf+hs", "236236h"]);
+    }
+
+    #[test]
+    fn jump_close_and_far() {
+        assert_all("j.D", &["j.D", "jD", "jd", "j. d", "J.D"]);
+        assert_all("j.2K", &["j.2k", "j2k"]);
+        assert_all("j.236K", &["j.236k", "jqcfk", "j.qcf+k"]);
+        assert_all("c.S", &["c.S", "cS", "cs", "cl.s", "cls"]);
+        assert_all("f.S", &["f.S", "fS", "fs"]);
+    }
+
+    #[test]
+    fn sol_and_ky() {
+        // Volcanic Viper, Gun Flame and Vapor Thrust
+        assert_all("623S", &["dp s", "623s"]);
+        assert_all("236P", &["qcf p"]);
+        // Sol's Bandit Revolver follow-up
+        assert_all("236K~K", &["236k~k", "qcf+k ~ k"]);
+    }
+
+    #[test]
+    fn potemkin_and_command_grabs() {
+        assert_all("632146P", &["360p", "360+p", "hcbf p", "spd p", "632146p"]);
+        assert_all("632146H", &["360 hs"]);
+    }
+
+    #[test]
+    fn leo_stance_charge_and_release() {
+        assert_all("bt.P", &["bt.p", "btp", "bt. P"]);
+        assert_all("bt.214K", &["bt.214k", "bt qcb k"]);
+        assert_all("[4]6S", &["[4]6s", "[4] 6 S", "[4]6+s"]);
+        assert_all("]S[", &["]s[", "] S ["]);
+        assert_all("]H[", &["]hs["]);
+    }
+
+    #[test]
+    fn may_axl_and_held_buttons() {
+        // charge specials like May's dolphins
+        assert_all("[4]6H", &["[4]6hs"]);
+        assert_all("[2]8S", &["[2]8 s"]);
+        // held buttons like Nagoriyuki's and Jack-O's
+        assert_all("236[S]", &["236[s]", "qcf [s]"]);
+        assert_all("5[D]", &["5[d]"]);
+    }
+
+    #[test]
+    fn nagoriyuki_and_target_combos() {
+        assert_all("f.SS", &["f.ss", "fss", "f.S S"]);
+        assert_all("f.SSS", &["f.sss"]);
+        assert_all("6HH", &["6hh", "6 H H", "6hshs"]);
+        // only normals chain like that, a repeated special stays two inputs
+        assert_all("236S 236S", &["236s236s"]);
+    }
+
+    #[test]
+    fn goldlewis_behemoth_typhoon_directions() {
+        // two of the eight half circles have names, the rest are only written out
+        assert_all("41236H", &["hcf hs", "41236 h"]);
+        assert_all("63214H", &["hcb+h", "6 3 2 1 4 hs"]);
+        assert_all("21478H", &["21478hs", "2 1 4 7 8 h"]);
+        assert_all("89632H", &["89632 hs"]);
+        // and stay one input rather than a special and a direction
+        assert_all("236H~8", &["236h~8"]);
+    }
+
+    #[test]
+    fn venom_and_dizzy_charge_directions() {
+        assert_all("[4]6S", &["[4]6 s"]);
+        assert_all("[2]8H", &["[2]8hs"]);
+    }
+
+    #[test]
+    fn happy_chaos_and_stance_buttons() {
+        // buttons pressed alone out of a stance, or held
+        assert_all("S", &["s"]);
+        assert_all("[H]", &["[hs]", "[ h ]"]);
+        assert_all("236S~S", &["236s ~ s"]);
+    }
+
+    #[test]
+    fn testament_zato_and_held_specials() {
+        assert_all("236[S]", &["236 [s]"]);
+        assert_all("214[K]", &["qcb[k]", "214 [k]"]);
+        assert_all("]K[", &["]k["]);
+    }
+
+    #[test]
+    fn bridget_anji_and_follow_ups() {
+        assert_all("236K~K", &["236k ~ k"]);
+        assert_all("236H~P", &["qcf hs ~ p"]);
+        assert_all("236S~K~K", &["236s~k~k"]);
+        // follow-ups that are only a direction
+        assert_all("22S~8", &["22s~8", "22s ~ 8"]);
+        assert_all("214K~6~P", &["214k~6~p"]);
+    }
+
+    #[test]
+    fn air_specials_and_hovers() {
+        // Millia, Chipp, Testament and Asuka all have air versions of specials
+        assert_all("j.214K", &["j.214k", "j qcb k"]);
+        assert_all("j.236S", &["j236s"]);
+        assert_all("j.[D]", &["j[d]", "j.[d]"]);
+    }
+
+    #[test]
+    fn faust_johnny_and_baiken() {
+        // Faust's pogo and its follow-ups
+        assert_all("22P", &["22p", "2 2 p"]);
+        assert_all("22P~K", &["22p~k"]);
+        // Johnny's held Mist Finer
+        assert_all("236[S]", &["qcf[s]"]);
+        // Baiken's Tatami Gaeshi on the ground and in the air
+        assert_all("236K", &["qcf k"]);
+        assert_all("j.236K", &["j.qcfk"]);
+    }
+
+    #[test]
+    fn ino_slayer_and_sin() {
+        assert_all("214K", &["qcb k"]);
+        assert_all("j.236K", &["j236k"]);
+        // Slayer's Dandy Step and the attacks out of it
+        assert_all("214P~P", &["214p~p"]);
+        assert_all("214P~K", &["qcbp ~ k"]);
+        // Sin's Beak Driver follow-up and Hawk Baker
+        assert_all("236H~H", &["236hs~hs"]);
+        assert_all("623S", &["srk s"]);
+    }
+
+    #[test]
+    fn aba_elphelt_and_jam() {
+        assert_all("236K", &["236 k"]);
+        assert_all("214S", &["qcbs"]);
+        assert_all("236S~P", &["236s~p"]);
+        // Jam's dive kick
+        assert_all("j.2K", &["j2k", "j.2 k"]);
+        assert_all("236P", &["qcf p"]);
+    }
+
+    #[test]
+    fn ramlethal_giovanna_and_bedman() {
+        assert_all("214P", &["qcb p"]);
+        assert_all("623P", &["dp p"]);
+        // Giovanna's Trovao in the air
+        assert_all("j.214K", &["jqcbk"]);
+        assert_all("236S", &["236s"]);
+        assert_all("j.236S", &["j. qcf s"]);
+        assert_all("632146S", &["360 s"]);
+    }
+
+    #[test]
+    fn chained_inputs() {
+        assert_all("236S 236S", &["236s 236s", "236s236s"]);
+        assert_all("214P~P", &["214p~p", "qcb p ~ p"]);
+    }
+
+    #[test]
+    fn bare_buttons() {
+        assert_all("D", &["d"]);
+        assert_all("H", &["hs"]);
+    }
+
+    #[test]
+    fn leaves_everything_else_alone() {
+        for query in ["Gun Flame", "dp", "dust", "ks", "5", "j.", "c.k", "f.h", "st.s", "st s", "sts", "236k~", "]s", "bt", "Brynhildr Stance", "~8", "22s~8s2", ""] {
+            assert_eq!(canonical(query), query, "canonical({:?})", query);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::SplitSink;
+use futures_util::SinkExt;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::capabilities::{self, Capabilities};
+use crate::config::LongReplies;
+use crate::ratelimit::RateLimiter;
+use crate::WsStream;
+
+pub type WsSink = SplitSink<WsStream, Message>;
+
+// replies beyond this many waiting to be sent are dropped rather than piling up
+const QUEUE_SIZE: usize = 256;
+// Twitch rejects chat messages longer than this many characters
+pub const MESSAGE_LIMIT: usize = 500;
+// a long reply is never split into more messages than this, the last one gets truncated instead
+const MAX_PARTS: usize = 3;
+const ELLIPSIS: &str = "…";
+
+// a reply that couldn't go out within this long is stale, chat has moved on
+const MAX_DELAY: Duration = Duration::from_secs(20);
+
+#[derive(Debug, Clone)]
+pub enum Outbound {
+    // reply_to is the id of the message this answers, which Twitch shows as a thread
+    Privmsg { channel: String, text: String, reply_to: Option<String> },
+    // anything that isn't chat, e.g. PONG, JOIN and PART. These aren't rate limited.
+    Raw(String),
+}
+
+enum Control {
+    // replaces the connection the writer sends on, closing the previous one
+    Attach(WsSink, Arc<Capabilities>),
+    Privileged { channel: String, privileged: bool },
+    SlowMode { channel: String, slow: Duration },
+}
+
+// Handle to the writer task. Sending only enqueues, the writer owns delivery so a slow or
+// failing send never blocks reading from the connection.
+#[derive(Clone)]
+pub struct Outbox {
+    queue: mpsc::Sender<Outbound>,
+    control: mpsc::UnboundedSender<Control>,
+}
+
+impl Outbox {
+    pub fn spawn() -> Outbox {
+        let (queue, queue_rx) = mpsc::channel(QUEUE_SIZE);
+        let (control, control_rx) = mpsc::unbounded_channel();
+        tokio::spawn(writer(queue_rx, control_rx));
+        Outbox { queue, control }
+    }
+
+    pub fn say(&self, channel: &str, text: String, reply_to: Option<String>) {
+        self.enqueue(Outbound::Privmsg { channel: channel.to_string(), text, reply_to });
+    }
+
+    pub fn raw(&self, line: String) {
+        self.enqueue(Outbound::Raw(line));
+    }
+
+    pub fn attach(&self, sink: WsSink, capabilities: Arc<Capabilities>) {
+        self.control(Control::Attach(sink, capabilities));
+    }
+
+    // from the badges in USERSTATE
+    pub fn set_privileged(&self, channel: &str, privileged: bool) {
+        self.control(Control::Privileged { channel: channel.to_string(), privileged });
+    }
+
+    // from the slow tag in ROOMSTATE
+    pub fn set_slow_mode(&self, channel: &str, slow: Duration) {
+        self.control(Control::SlowMode { channel: channel.to_string(), slow });
+    }
+
+    fn control(&self, control: Control) {
+        if self.control.send(control).is_err() {
+            eprintln!("Writer task is gone");
+        }
+    }
+
+    fn enqueue(&self, outbound: Outbound) {
+        if let Err(err) = self.queue.try_send(outbound) {
+            eprintln!("Dropping outbound message: {}", err);
+        }
+    }
+}
+
+struct Pending {
+    channel: String,
+    text: String,
+    reply_to: Option<String>,
+    queued_at: Instant,
+}
+
+// Chat messages wait in `pending` until the rate limiter lets them through, in order per channel.
+// They also stay there while there is no connection to send them on.
+async fn writer(mut queue: mpsc::Receiver<Outbound>, mut control: mpsc::UnboundedReceiver<Control>) {
+    let mut sink: Option<WsSink> = None;
+    // reply tags are only understood on connections that acknowledged twitch.tv/tags
+    let mut tags = false;
+    let mut limiter = RateLimiter::new();
+    let mut pending: VecDeque<Pending> = VecDeque::new();
+
+    loop {
+        let now = Instant::now();
+        pending.retain(|p| {
+            let stale = now.saturating_duration_since(p.queued_at) > MAX_DELAY;
+            if stale {
+                eprintln!("Dropping reply to #{} that was rate limited for too long: {}", p.channel, p.text);
+            }
+            !stale
+        });
+
+        let mut next_wake = None;
+        if let Some(current) = sink.as_mut() {
+            let mut sendable = None;
+            for (i, p) in pending.iter().enumerate() {
+                // only the oldest message of each channel is eligible to keep replies in order
+                if pending.iter().take(i).any(|earlier| earlier.channel == p.channel) {
+                    continue;
+                }
+                let wait = limiter.wait(&p.channel, now);
+                if wait.is_zero() {
+                    sendable = Some(i);
+                    break;
+                }
+                next_wake = Some(next_wake.map_or(wait, |w: Duration| w.min(wait)));
+            }
+            if let Some(p) = sendable.and_then(|i| pending.remove(i)) {
+                limiter.record(&p.channel, now);
+                let outbound = Outbound::Privmsg { channel: p.channel, text: p.text, reply_to: p.reply_to.filter(|_| tags) };
+                if let Err(err) = current.send(format_msg(&outbound)).await {
+                    // the reader notices the dead connection and a new one gets attached
+                    eprintln!("Failed to send {:?}: {}", outbound, err);
+                    sink = None;
+                }
+                continue;
+            }
+        }
+
+        tokio::select! {
+            biased;
+            ctrl = control.recv() => match ctrl {
+                Some(Control::Attach(new_sink, capabilities)) => {
+                    tags = capabilities.has(capabilities::TAGS);
+                    if let Some(mut old_sink) = sink.replace(new_sink) {
+                        if let Err(err) = old_sink.close().await {
+                            println!("Error closing old connection: {}", err);
+                        }
+                    }
+                },
+                Some(Control::Privileged { channel, privileged }) => limiter.set_privileged(&channel, privileged),
+                Some(Control::SlowMode { channel, slow }) => limiter.set_slow_mode(&channel, slow),
+                None => return,
+            },
+            outbound = queue.recv() => match outbound {
+                Some(Outbound::Privmsg { channel, text, reply_to }) => {
+                    if pending.len() >= QUEUE_SIZE {
+                        eprintln!("Dropping reply to #{}, too many replies waiting: {}", channel, text);
+                    } else {
+                        pending.push_back(Pending { channel, text, reply_to, queued_at: Instant::now() });
+                    }
+                },
+                Some(Outbound::Raw(line)) => {
+                    let Some(current) = sink.as_mut() else {
+                        // JOINs are resent on connect and PONGs are meaningless on a new connection
+                        println!("Not connected, dropping {}", line);
+                        continue;
+                    };
+                    if let Err(err) = current.send(Message::Text(line.clone())).await {
+                        eprintln!("Failed to send {}: {}", line, err);
+                        sink = None;
+                    }
+                },
+                None => return,
+            },
+            _ = tokio::time::sleep(next_wake.unwrap_or(MAX_DELAY)), if next_wake.is_some() => {},
+        }
+    }
+}
+
+// Makes a reply fit in Twitch's message limit, splitting or truncating it on field boundaries.
+// No part starts with "/" or ".", which Twitch would run as a chat command, since what follows
+// a cut can be text a viewer typed.
+pub fn fit_message(text: &str, limit: usize, mode: LongReplies) -> Vec<String> {
+    let max_parts = match mode {
+        LongReplies::Split => MAX_PARTS,
+        LongReplies::Truncate => 1,
+    };
+    let mut parts = vec![];
+    let mut rest = without_command(text);
+    while !rest.is_empty() {
+        if rest.chars().count() <= limit {
+            parts.push(rest.to_string());
+            break;
+        }
+        if parts.len() + 1 == max_parts {
+            let room = limit - ELLIPSIS.chars().count();
+            let cut = break_point(rest, room);
+            parts.push(format!("{}{}", rest[..cut].trim_end(), ELLIPSIS));
+            break;
+        }
+        let cut = break_point(rest, limit);
+        parts.push(rest[..cut].trim_end().to_string());
+        rest = without_command(rest[cut..].trim_start().trim_start_matches('|'));
+    }
+    parts
+}
+
+fn without_command(text: &str) -> &str {
+    text.trim_start_matches(|c: char| c == '/' || c == '.' || c.is_whitespace()).trim_end()
+}
+
+// The byte index to cut `text` at so that the first part has at most `limit` characters.
+// Prefers the end of a field like "startup=(7)" or "a | b", then any whitespace, then anywhere.
+fn break_point(text: &str, limit: usize) -> usize {
+    let max = text.char_indices().nth(limit).map(|(i, _)| i).unwrap_or(text.len());
+    // one character more, so a field ending exactly at the limit is still seen as one
+    let head = &text[..text[max..].chars().next().map_or(max, |c| max + c.len_utf8())];
+    let field_end = head.rfind(") ").map(|i| i + 1)
+        .or_else(|| head.rfind(" | "));
+    if let Some(i) = field_end.filter(|i| *i > 0) {
+        return i;
+    }
+    match head.rfind(char::is_whitespace).filter(|i| *i > 0) {
+        Some(i) => i,
+        None => max,
+    }
+}
+
+fn format_msg(outbound: &Outbound) -> Message {
+    match outbound {
+        Outbound::Privmsg { channel, text, reply_to: Some(parent) } => {
+            Message::Text(format!("@reply-parent-msg-id={} PRIVMSG #{} :{}", parent, channel, text))
+        },
+        Outbound::Privmsg { channel, text, reply_to: None } => Message::Text(format!("PRIVMSG #{} :{}", channel, text)),
+        Outbound::Raw(line) => Message::Text(line.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::LongReplies;
+
+    use super::fit_message;
+
+    #[test]
+    fn short_replies_are_left_alone() {
+        assert_eq!(fit_message("5K: startup=(4)", 500, LongReplies::Split), ["5K: startup=(4)"]);
+    }
+
+    #[test]
+    fn splits_on_field_boundaries() {
+        let parts = fit_message("dmg=(20) guard=(All) startup=(7)", 20, LongReplies::Split);
+        assert_eq!(parts, ["dmg=(20) guard=(All)", "startup=(7)"]);
+        let parts = fit_message("a | bbbb | c", 10, LongReplies::Split);
+        assert_eq!(parts, ["a | bbbb", "c"]);
+    }
+
+    #[test]
+    fn truncates_past_the_last_part() {
+        let parts = fit_message("one two three four five six", 10, LongReplies::Truncate);
+        assert_eq!(parts, ["one two…"]);
+        let parts = fit_message("aa bb cc dd ee ff gg hh", 5, LongReplies::Split);
+        assert_eq!(parts, ["aa bb", "cc dd", "ee…"]);
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        let parts = fit_message("ああああ いいいい", 4, LongReplies::Split);
+        assert_eq!(parts, ["ああああ", "いいいい"]);
+        let parts = fit_message("ああああああ", 4, LongReplies::Truncate);
+        assert_eq!(parts, ["あああ…"]);
+    }
+
+    #[test]
+    fn no_part_starts_a_chat_command() {
+        let parts = fit_message("Unknown move 'aaaaaaaa /ban someone' for Sol", 22, LongReplies::Split);
+        assert_eq!(parts, ["Unknown move 'aaaaaaaa", "ban someone' for Sol"]);
+        assert_eq!(fit_message("./me dances", 500, LongReplies::Split), ["me dances"]);
+        assert!(fit_message(" / . ", 500, LongReplies::Split).is_empty());
+        for part in fit_message("x .timeout a 1 .timeout b 1 /ban c", 10, LongReplies::Split) {
+            assert!(!part.starts_with(['/', '.']), "{:?}", part);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// https://dev.twitch.tv/docs/irc/#rate-limits
+const WINDOW: Duration = Duration::from_secs(30);
+const NORMAL_LIMIT: usize = 20;
+// for channels where we're the broadcaster, a moderator or a VIP
+const PRIVILEGED_LIMIT: usize = 100;
+// Twitch drops messages from regular users sent faster than this in the same channel
+const NORMAL_CHANNEL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Remembers when each message in the last WINDOW was sent. Unlike a refilling bucket this never
+// lets more than `limit` messages into any WINDOW long stretch of time, which is what Twitch counts.
+#[derive(Debug, Clone)]
+struct SlidingWindow {
+    limit: usize,
+    sent: VecDeque<Instant>,
+}
+
+impl SlidingWindow {
+    fn new(limit: usize) -> SlidingWindow {
+        SlidingWindow { limit, sent: VecDeque::new() }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self.sent.front().is_some_and(|sent| now.saturating_duration_since(*sent) >= WINDOW) {
+            self.sent.pop_front();
+        }
+    }
+
+    fn wait(&mut self, now: Instant) -> Duration {
+        self.prune(now);
+        if self.sent.len() < self.limit {
+            return Duration::ZERO;
+        }
+        // the oldest one has to leave the window before there's room again
+        let oldest = self.sent[self.sent.len() - self.limit];
+        (oldest + WINDOW).saturating_duration_since(now)
+    }
+
+    fn record(&mut self, now: Instant) {
+        self.prune(now);
+        self.sent.push_back(now);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ChannelState {
+    // learned from the badges in USERSTATE
+    privileged: bool,
+    // learned from ROOMSTATE, zero when slow mode is off
+    slow: Duration,
+    last_sent: Option<Instant>,
+}
+
+// Keeps outbound PRIVMSGs under Twitch's limits. Exceeding them gets messages silently dropped
+// or the account locked out of chat for a while.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    // every message counts against this one
+    all: SlidingWindow,
+    // only messages to channels where we aren't privileged count against this one
+    normal: SlidingWindow,
+    channels: HashMap<String, ChannelState>,
+}
+
+impl RateLimiter {
+    pub fn new() -> RateLimiter {
+        RateLimiter {
+            all: SlidingWindow::new(PRIVILEGED_LIMIT),
+            normal: SlidingWindow::new(NORMAL_LIMIT),
+            channels: HashMap::new(),
+        }
+    }
+
+    pub fn set_privileged(&mut self, channel: &str, privileged: bool) {
+        self.channels.entry(channel.to_string()).or_default().privileged = privileged;
+    }
+
+    pub fn set_slow_mode(&mut self, channel: &str, slow: Duration) {
+        self.channels.entry(channel.to_string()).or_default().slow = slow;
+    }
+
+    // how long until a message can be sent to `channel`, zero if it can be sent now
+    pub fn wait(&mut self, channel: &str, now: Instant) -> Duration {
+        let state = self.channels.get(channel).cloned().unwrap_or_default();
+        let mut wait = self.all.wait(now);
+        if !state.privileged {
+            wait = wait.max(self.normal.wait(now));
+            // slow mode and the per-channel limit don't apply to moderators and VIPs
+            if let Some(last_sent) = state.last_sent {
+                let interval = state.slow.max(NORMAL_CHANNEL_INTERVAL);
+                wait = wait.max((last_sent + interval).saturating_duration_since(now));
+            }
+        }
+        wait
+    }
+
+    pub fn record(&mut self, channel: &str, now: Instant) {
+        let state = self.channels.entry(channel.to_string()).or_default();
+        state.last_sent = Some(now);
+        self.all.record(now);
+        if !state.privileged {
+            self.normal.record(now);
+        }
+    }
+}
+
+// broadcaster, moderator and VIP badges all lift the normal limits
+pub fn is_privileged_badge(badge: &str) -> bool {
+    matches!(badge, "broadcaster" | "moderator" | "vip")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{RateLimiter, NORMAL_LIMIT, PRIVILEGED_LIMIT, WINDOW};
+
+    // sends to `channels` in turn as fast as the limiter allows and returns when each went out
+    fn flood(limiter: &mut RateLimiter, channels: &[&str], start: Instant) -> Vec<Instant> {
+        let mut sent = vec![];
+        let step = Duration::from_millis(50);
+        let mut now = start;
+        let mut next = 0;
+        while now < start + WINDOW * 4 {
+            let channel = channels[next % channels.len()];
+            if limiter.wait(channel, now).is_zero() {
+                limiter.record(channel, now);
+                sent.push(now);
+                next += 1;
+            }
+            now += step;
+        }
+        sent
+    }
+
+    fn busiest_window(sent: &[Instant]) -> usize {
+        sent.iter().map(|start| sent.iter().filter(|t| **t >= *start && **t < *start + WINDOW).count()).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn normal_limit_holds_in_every_window() {
+        let channels = (0..50).map(|i| format!("channel{}", i)).collect::<Vec<String>>();
+        let channels = channels.iter().map(String::as_str).collect::<Vec<&str>>();
+        let mut limiter = RateLimiter::new();
+        let sent = flood(&mut limiter, &channels, Instant::now());
+        assert_eq!(busiest_window(&sent), NORMAL_LIMIT);
+    }
+
+    #[test]
+    fn privileged_limit_holds_in_every_window() {
+        let channels = (0..200).map(|i| format!("channel{}", i)).collect::<Vec<String>>();
+        let channels = channels.iter().map(String::as_str).collect::<Vec<&str>>();
+        let mut limiter = RateLimiter::new();
+        for channel in &channels {
+            limiter.set_privileged(channel, true);
+        }
+        let sent = flood(&mut limiter, &channels, Instant::now());
+        assert_eq!(busiest_window(&sent), PRIVILEGED_LIMIT);
+    }
+}
@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// An error that reconnecting won't fix, like a rejected token
+#[derive(Debug, Clone)]
+pub struct FatalError(pub String);
+
+impl fmt::Display for FatalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for FatalError {}
+
+pub fn is_fatal(err: &(dyn Error + 'static)) -> bool {
+    err.downcast_ref::<FatalError>().is_some()
+}
+
+// Capped exponential backoff with jitter. Each delay is picked uniformly between half
+// and all of the current step so that many clients don't retry in lockstep.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Backoff {
+        Backoff { base, max, attempt: 0 }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let step = self.base.saturating_mul(2u32.saturating_pow(self.attempt)).min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        let half = step / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new(Duration::from_secs(1), Duration::from_secs(300))
+    }
+}
+
+// a connection that stayed up this long is considered healthy and resets the backoff
+const HEALTHY_SESSION: Duration = Duration::from_secs(60);
+
+// Runs `session` until it fails fatally, waiting between attempts according to `backoff`.
+// A session that returns Ok was closed by the server and is reconnected as well.
+pub async fn supervise<F, Fut>(mut backoff: Backoff, mut session: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<(), Box<dyn Error>>>,
+{
+    loop {
+        let started = Instant::now();
+        let result = session().await;
+        if started.elapsed() >= HEALTHY_SESSION {
+            backoff.reset();
+        }
+
+        match result {
+            Err(err) if is_fatal(err.as_ref()) => {
+                eprintln!("Fatal connection error, giving up: {}", err);
+                return Err(err);
+            },
+            Err(err) => {
+                let delay = backoff.next_delay();
+                eprintln!("Connection lost: {}. Reconnecting in {:.1}s", err, delay.as_secs_f64());
+                tokio::time::sleep(delay).await;
+            },
+            Ok(()) => {
+                let delay = backoff.next_delay();
+                println!("Connection closed by server. Reconnecting in {:.1}s", delay.as_secs_f64());
+                tokio::time::sleep(delay).await;
+            },
+        }
+    }
+}
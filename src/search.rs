@@ -0,0 +1,171 @@
+use crate::framedata::{normalize, Character, Move};
+
+// candidates scoring within this much of the best one make a query ambiguous
+const AMBIGUITY_MARGIN: f64 = 0.05;
+const MAX_CANDIDATES: usize = 6;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<'a> {
+    pub found: &'a Move,
+    // 1.0 for an exact match, lower for looser ones. Moves matching the same way score the
+    // same regardless of their length.
+    pub score: f64,
+}
+
+#[derive(Debug, Clone)]
+pub enum SearchResult<'a> {
+    Found(&'a Move),
+    // no single move stands out, best first
+    Ambiguous(Vec<Candidate<'a>>),
+    NotFound,
+}
+
+// Every move of `character` that matches `query` at all, best first
+pub fn rank_moves<'a>(character: &'a Character, query: &str) -> Vec<Candidate<'a>> {
+    let query = normalize(query);
+    if query.is_empty() {
+        return vec![];
+    }
+    let mut candidates = character.moves.iter()
+        .map(|m| Candidate { found: m, score: score(&query, &m.input).max(score(&query, &m.name) * 0.99) })
+        .filter(|c| c.score > 0.0)
+        .collect::<Vec<Candidate>>();
+    // stable, so equally good moves keep dustloop's order
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates
+}
+
+// Picks the move `query` is after, unless several fit about equally well. `!fd ky 6` could be any
+// of 6P/6K/6S/6H and guessing one would just be wrong most of the time.
+pub fn find_move<'a>(character: &'a Character, query: &str) -> SearchResult<'a> {
+    let candidates = rank_moves(character, query);
+    let Some(best) = candidates.first().copied() else {
+        return SearchResult::NotFound;
+    };
+    let close = candidates.iter()
+        .take_while(|c| best.score - c.score < AMBIGUITY_MARGIN)
+        .take(MAX_CANDIDATES)
+        .copied()
+        .collect::<Vec<Candidate>>();
+    // an exact match always wins, e.g. "5k" over "5k~5k"
+    if close.len() == 1 || (best.score >= 1.0 && close.iter().filter(|c| c.score >= 1.0).count() == 1) {
+        SearchResult::Found(best.found)
+    } else {
+        SearchResult::Ambiguous(close)
+    }
+}
+
+// `query` is normalized, `raw` is an input or name as dustloop has it
+fn score(query: &str, raw: &str) -> f64 {
+    let candidate = normalize(raw);
+    if candidate.is_empty() {
+        return 0.0;
+    }
+    let boundaries = boundaries(raw);
+    if candidate == query {
+        1.0
+    } else if candidate.starts_with(query) && boundaries.contains(&query.len()) {
+        // "6" for 6P but not for 623S, "gun" for Gun Flame
+        0.8
+    } else if candidate.starts_with(query) {
+        0.6
+    } else if boundaries.iter().any(|&b| candidate[b..].starts_with(query) && boundaries.contains(&(b + query.len()))) {
+        // a whole later word, "throw" for Ground Throw
+        0.5
+    } else if candidate.contains(query) {
+        0.4
+    } else {
+        0.0
+    }
+}
+
+// Byte offsets into the normalized form of `raw` where a word starts or ends, counting a switch
+// between digits and letters as one so "5K~5K" splits into 5, K, 5 and K
+fn boundaries(raw: &str) -> Vec<usize> {
+    let mut boundaries = vec![0];
+    let mut len = 0;
+    let mut previous: Option<char> = None;
+    let mut separated = false;
+    for c in raw.chars() {
+        let kept = normalize(&c.to_string());
+        if kept.is_empty() {
+            separated = true;
+            continue;
+        }
+        if let Some(p) = previous {
+            if separated || p.is_ascii_digit() != c.is_ascii_digit() {
+                boundaries.push(len);
+            }
+        }
+        len += kept.len();
+        previous = Some(c);
+        separated = false;
+    }
+    boundaries.push(len);
+    boundaries
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framedata::{Character, Move};
+
+    use super::{find_move, SearchResult};
+
+    fn sol() -> Character {
+        Character {
+            name: "Sol Badguy".to_string(),
+            moves: vec![
+                Move::test("5K"),
+                Move::test("5K~5K"),
+                Move::test("6D").named("Ground Throw"),
+                Move::test("j.6D").named("Air Throw"),
+                Move::test("236P").named("Gun Flame"),
+                Move::test("236P~P").named("Gun Flame (Feint)"),
+                Move::test("41236K").named("Wild Throw"),
+                Move::test("623S").named("Volcanic Viper"),
+            ],
+        }
+    }
+
+    fn ky() -> Character {
+        Character {
+            name: "Ky Kiske".to_string(),
+            moves: ["5P", "6P", "6K", "6S", "6H", "623S", "632146H"].into_iter().map(Move::test).collect(),
+        }
+    }
+
+    fn found(character: &Character, query: &str) -> Option<String> {
+        match find_move(character, query) {
+            SearchResult::Found(found) => Some(found.input.clone()),
+            _ => None,
+        }
+    }
+
+    fn candidates(character: &Character, query: &str) -> Vec<String> {
+        match find_move(character, query) {
+            SearchResult::Ambiguous(candidates) => candidates.iter().map(|c| c.found.input.clone()).collect(),
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn exact_input_wins() {
+        assert_eq!(found(&sol(), "5k"), Some("5K".to_string()));
+        assert_eq!(found(&sol(), "5k~5k"), Some("5K~5K".to_string()));
+        assert_eq!(found(&sol(), "gun flame"), Some("236P".to_string()));
+    }
+
+    #[test]
+    fn unique_partial_matches() {
+        assert_eq!(found(&sol(), "viper"), Some("623S".to_string()));
+        assert_eq!(found(&ky(), "63"), Some("632146H".to_string()));
+        assert!(matches!(find_move(&sol(), "dragon"), SearchResult::NotFound));
+    }
+
+    #[test]
+    fn same_kind_of_match_is_ambiguous_regardless_of_length() {
+        assert_eq!(candidates(&ky(), "6"), ["6P", "6K", "6S", "6H"]);
+        assert_eq!(candidates(&sol(), "throw"), ["6D", "j.6D", "41236K"]);
+        assert_eq!(candidates(&sol(), "gun"), ["236P", "236P~P"]);
+    }
+}
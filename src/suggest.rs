@@ -0,0 +1,98 @@
+use crate::framedata::{normalize, Character, FrameData};
+
+// below this similarity a name isn't worth suggesting
+const MIN_SCORE: f64 = 0.75;
+pub const MAX_SUGGESTIONS: usize = 3;
+
+// Characters whose name looks like a misspelling of `query`, best first
+pub fn characters<'a>(data: &'a FrameData, query: &str) -> Vec<&'a str> {
+    rank(data.characters.iter().map(|c| (c.name.as_str(), similarity(query, &c.name))))
+}
+
+// Moves of `character` whose name or input looks like a misspelling of `query`, best first
+pub fn moves<'a>(character: &'a Character, query: &str) -> Vec<&'a str> {
+    rank(character.moves.iter().map(|m| {
+        let score = similarity(query, &m.name).max(similarity(query, &m.input));
+        let shown = if m.name.is_empty() { m.input.as_str() } else { m.name.as_str() };
+        (shown, score)
+    }))
+}
+
+fn rank<'a>(scored: impl Iterator<Item = (&'a str, f64)>) -> Vec<&'a str> {
+    let mut scored = scored.filter(|(_, score)| *score >= MIN_SCORE).collect::<Vec<(&str, f64)>>();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let mut names: Vec<&str> = vec![];
+    for (name, _) in scored {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+        if names.len() == MAX_SUGGESTIONS {
+            break;
+        }
+    }
+    names
+}
+
+// Between 0 and 1. Compares the whole strings as well as word by word, so a typo in one word
+// of "dragn install" still scores well against "Dragon Install".
+pub fn similarity(query: &str, candidate: &str) -> f64 {
+    let whole = strsim::jaro_winkler(&normalize(query), &normalize(candidate));
+
+    let candidate_words = candidate.split_whitespace().map(normalize).filter(|w| !w.is_empty()).collect::<Vec<String>>();
+    let query_words = query.split_whitespace().map(normalize).filter(|w| !w.is_empty()).collect::<Vec<String>>();
+    if candidate_words.is_empty() || query_words.is_empty() {
+        return whole;
+    }
+    let by_word = query_words.iter()
+        .map(|q| candidate_words.iter().map(|c| strsim::jaro_winkler(q, c)).fold(0.0, f64::max))
+        .sum::<f64>() / query_words.len() as f64;
+
+    whole.max(by_word)
+}
+
+// "did you mean: a, b?" or nothing when there are no suggestions
+pub fn did_you_mean(suggestions: &[&str]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!(" — did you mean: {}?", suggestions.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{characters, did_you_mean, moves};
+    use crate::framedata::{Character, FrameData, Move};
+
+    fn sol() -> Character {
+        Character {
+            name: "Sol Badguy".to_string(),
+            moves: vec![
+                Move::test("236P").named("Gun Flame"),
+                Move::test("623S").named("Volcanic Viper"),
+                Move::test("214K").named("Bandit Revolver"),
+                Move::test("632146H").named("Tyrant Rave"),
+                Move::test("214214P").named("Dragon Install"),
+                Move::test("214214P~H").named("Dragon Install Sakkai"),
+            ],
+        }
+    }
+
+    #[test]
+    fn misspelled_move_names() {
+        let sol = sol();
+        let suggested = moves(&sol, "dragn install");
+        assert!(suggested.contains(&"Dragon Install"), "{:?}", suggested);
+        assert!(suggested.contains(&"Dragon Install Sakkai"), "{:?}", suggested);
+        assert_eq!(moves(&sol, "volcanc viper").first(), Some(&"Volcanic Viper"));
+    }
+
+    #[test]
+    fn nothing_for_unrelated_words() {
+        assert!(moves(&sol(), "lunchbox").is_empty());
+        let data = FrameData { characters: vec![sol()] };
+        assert!(characters(&data, "lunchbox").is_empty());
+        assert_eq!(characters(&data, "sool"), vec!["Sol Badguy"]);
+        assert_eq!(did_you_mean(&[]), "");
+    }
+}
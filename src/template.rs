@@ -0,0 +1,195 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::framedata::{Character, Move};
+
+pub const DEFAULT_TEMPLATE: &str =
+    "{input}: dmg=({damage}) guard=({guard}) startup=({startup}) active=({active}) recov=({recovery}) block=({onblock}) hit=({onhit}) atklvl=({level})";
+
+// A reply format for moves, e.g. "{input} | S:{startup} A:{active} R:{recovery} | oB:{onblock}".
+// Literal braces are written as "{{" and "}}". Parsed when the config loads so a typo in a
+// placeholder is reported right away instead of showing up in chat.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Field(Placeholder),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    Character, Name, Input, Damage, Guard, Startup, Active, Recovery, OnBlock, OnHit, Level,
+}
+
+impl Placeholder {
+    fn from_name(name: &str) -> Option<Placeholder> {
+        Some(match name {
+            "character" | "char" => Placeholder::Character,
+            "name" => Placeholder::Name,
+            "input" => Placeholder::Input,
+            "damage" | "dmg" => Placeholder::Damage,
+            "guard" => Placeholder::Guard,
+            "startup" => Placeholder::Startup,
+            "active" => Placeholder::Active,
+            "recovery" | "recov" => Placeholder::Recovery,
+            "onblock" | "block" => Placeholder::OnBlock,
+            "onhit" | "hit" => Placeholder::OnHit,
+            "level" | "atklvl" => Placeholder::Level,
+            _ => return None,
+        })
+    }
+
+    fn value<'a>(&self, character: &'a Character, found: &'a Move) -> &'a str {
+        match self {
+            Placeholder::Character => &character.name,
+            Placeholder::Name => &found.name,
+            Placeholder::Input => &found.input,
+            Placeholder::Damage => &found.damage,
+            Placeholder::Guard => &found.guard,
+            Placeholder::Startup => &found.startup,
+            Placeholder::Active => &found.active,
+            Placeholder::Recovery => &found.recovery,
+            Placeholder::OnBlock => &found.onblock,
+            Placeholder::OnHit => &found.onhit,
+            Placeholder::Level => &found.level,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TemplateError(String);
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, TemplateError> {
+        let mut segments = vec![];
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                },
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(TemplateError(format!("unclosed placeholder '{{{}'", name))),
+                        }
+                    }
+                    let placeholder = Placeholder::from_name(name.trim()).ok_or_else(|| TemplateError(format!(
+                        "unknown placeholder '{{{}}}', expected one of {{character}}, {{name}}, {{input}}, {{damage}}, {{guard}}, \
+                         {{startup}}, {{active}}, {{recovery}}, {{onblock}}, {{onhit}}, {{level}}", name)))?;
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Field(placeholder));
+                },
+                '}' => return Err(TemplateError("unmatched '}', write '}}' for a literal brace".to_string())),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        if !segments.iter().any(|s| matches!(s, Segment::Field(_))) {
+            return Err(TemplateError("template has no placeholders".to_string()));
+        }
+        Ok(Template { segments })
+    }
+
+    pub fn render(&self, character: &Character, found: &Move) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Field(placeholder) => out.push_str(placeholder.value(character, found)),
+            }
+        }
+        out
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template::parse(DEFAULT_TEMPLATE).expect("default template is valid")
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = TemplateError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Template::parse(&source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Template, DEFAULT_TEMPLATE};
+    use crate::framedata::{Character, Move};
+
+    fn sol() -> Character {
+        Character { name: "Sol Badguy".to_string(), moves: vec![] }
+    }
+
+    fn gun_flame() -> Move {
+        Move {
+            name: "Gun Flame".to_string(),
+            input: "236P".to_string(),
+            damage: "20".to_string(),
+            guard: "All".to_string(),
+            startup: "16".to_string(),
+            active: "Total 35".to_string(),
+            recovery: "29".to_string(),
+            onblock: "-10".to_string(),
+            onhit: "-8".to_string(),
+            level: "1".to_string(),
+        }
+    }
+
+    fn error(source: &str) -> String {
+        Template::parse(source).expect_err(source).to_string()
+    }
+
+    #[test]
+    fn default_renders_like_the_old_format() {
+        let found = gun_flame();
+        let old = format!("{}: dmg=({}) guard=({}) startup=({}) active=({}) recov=({}) block=({}) hit=({}) atklvl=({})",
+            found.input, found.damage, found.guard, found.startup, found.active, found.recovery, found.onblock, found.onhit, found.level);
+        assert_eq!(Template::default().render(&sol(), &found), old);
+        assert_eq!(Template::parse(DEFAULT_TEMPLATE).unwrap(), Template::default());
+    }
+
+    #[test]
+    fn placeholders_and_escaped_braces() {
+        let template = Template::parse("{{{char}}} {name} ({ input }) {{S:{startup}}}").unwrap();
+        assert_eq!(template.render(&sol(), &gun_flame()), "{Sol Badguy} Gun Flame (236P) {S:16}");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(error("{input} {startup").starts_with("unclosed placeholder '{startup'"));
+        assert!(error("{input} }").starts_with("unmatched '}'"));
+        assert!(error("{input} {speed}").starts_with("unknown placeholder '{speed}'"));
+        assert_eq!(error("just text {{}}"), "template has no placeholders");
+    }
+}